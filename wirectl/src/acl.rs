@@ -0,0 +1,150 @@
+//! Firewall rules between the peers of an interface
+//!
+//! An [`Acl`] is compiled into nftables rules which recognize a peer by the
+//! source address of its packets: Wireguard only accepts the packets of a peer
+//! coming from one of its allowed IPs. Rules are evaluated in order, and the
+//! first matching one decides. Replies to accepted connections are always let
+//! through.
+//!
+//! ```
+//! use wirectl::acl::*;
+//! use wirectl::types::PublicKey;
+//!
+//! let laptop = PublicKey::from([1; 32]);
+//! let server = PublicKey::from([2; 32]);
+//! let acl = Acl::new(AclAction::Deny)
+//!     .add_rule(AclRule::allow(laptop.clone(), AclTarget::Peer(server)))
+//!     .add_rule(
+//!         AclRule::allow(laptop, AclTarget::Network("192.168.1.0/24".parse().unwrap()))
+//!             .set_protocol(AclProtocol::Tcp)
+//!             .set_ports(PortRange::new(8000, 8080)),
+//!     );
+//! assert_eq!(acl.rules.len(), 2);
+//! ```
+use crate::types::*;
+use ipnetwork::IpNetwork;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What happens to the matching packets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AclAction {
+    Allow,
+    Deny,
+}
+
+/// Where the packets of a peer go
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AclTarget {
+    /// The allowed IPs of another peer
+    Peer(PublicKey),
+    /// Any network, inside or outside the tunnel
+    Network(IpNetwork),
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AclProtocol {
+    Tcp,
+    Udp,
+}
+
+/// An inclusive range of destination ports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    pub fn new(start: u16, end: u16) -> Self {
+        Self { start, end }
+    }
+
+    pub fn single(port: u16) -> Self {
+        Self::new(port, port)
+    }
+}
+
+/// Traffic from a peer to a target
+///
+/// Ports can only be matched together with a protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AclRule {
+    pub action: AclAction,
+    pub from: PublicKey,
+    pub to: AclTarget,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub protocol: Option<AclProtocol>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ports: Option<PortRange>,
+}
+
+impl AclRule {
+    pub fn new(action: AclAction, from: PublicKey, to: AclTarget) -> Self {
+        Self {
+            action,
+            from,
+            to,
+            protocol: None,
+            ports: None,
+        }
+    }
+
+    pub fn allow(from: PublicKey, to: AclTarget) -> Self {
+        Self::new(AclAction::Allow, from, to)
+    }
+
+    pub fn deny(from: PublicKey, to: AclTarget) -> Self {
+        Self::new(AclAction::Deny, from, to)
+    }
+
+    pub fn set_protocol(mut self, protocol: AclProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    pub fn set_ports(mut self, ports: PortRange) -> Self {
+        self.ports = Some(ports);
+        self
+    }
+
+    /// Whether the ports can be matched
+    pub fn is_valid(&self) -> bool {
+        match self.ports {
+            Some(ports) => self.protocol.is_some() && ports.start <= ports.end,
+            None => true,
+        }
+    }
+}
+
+/// Access control of the traffic coming from the peers of an interface
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Acl {
+    /// The action for the packets no rule matches
+    pub default: AclAction,
+    pub rules: Vec<AclRule>,
+}
+
+impl Acl {
+    pub fn new(default: AclAction) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn add_rule(mut self, rule: AclRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+}
@@ -0,0 +1,261 @@
+use super::{normalize, AllowedIpsTable};
+use crate::types::*;
+use ipnetwork::IpNetwork;
+use std::{collections::HashMap, fmt};
+
+/// A problem with the allowed IPs of several peers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllowedIpConflict {
+    /// The same prefix is assigned to several peers, only the last one gets it
+    Duplicate {
+        allowed_ip: IpNetwork,
+        peers: Vec<PublicKey>,
+    },
+    /// `allowed_ip` of `peer` is inside `covering` of `other`, so it takes a
+    /// part of the range of `other`
+    Overlap {
+        allowed_ip: IpNetwork,
+        peer: PublicKey,
+        covering: IpNetwork,
+        other: PublicKey,
+    },
+    /// Applying the changes moves `allowed_ip` from one peer to another
+    Moved {
+        allowed_ip: IpNetwork,
+        from: PublicKey,
+        to: PublicKey,
+    },
+}
+
+impl fmt::Display for AllowedIpConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate { allowed_ip, peers } => {
+                write!(f, "Allowed IP {} is assigned to peers ", allowed_ip)?;
+                for (i, peer) in peers.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", peer.to_base64())?;
+                }
+                Ok(())
+            }
+            Self::Overlap {
+                allowed_ip,
+                peer,
+                covering,
+                other,
+            } => write!(
+                f,
+                "Allowed IP {} of peer {} shadows a part of {} of peer {}",
+                allowed_ip,
+                peer.to_base64(),
+                covering,
+                other.to_base64()
+            ),
+            Self::Moved {
+                allowed_ip,
+                from,
+                to,
+            } => write!(
+                f,
+                "Allowed IP {} moves from peer {} to peer {}",
+                allowed_ip,
+                from.to_base64(),
+                to.to_base64()
+            ),
+        }
+    }
+}
+
+/// Find duplicate and overlapping allowed IPs between `peers`
+///
+/// Prefixes are compared without their host bits. Overlaps within a single
+/// peer are not reported.
+pub fn find_conflicts(peers: &[Peer]) -> Vec<AllowedIpConflict> {
+    let assignments: Vec<_> = peers
+        .iter()
+        .map(|peer| (peer.public_key.clone(), canonical_all(&peer.allow_ips)))
+        .collect();
+    let mut conflicts = find_duplicates(&assignments);
+    conflicts.extend(find_overlaps(&assignments));
+    conflicts
+}
+
+/// Find the conflicts which applying `conf` to `device` would cause
+///
+/// Reports the prefixes assigned to several peers by `conf` itself, the
+/// prefixes moved away from a peer of `device`, and the overlaps between the
+/// peers afterwards.
+pub fn find_conflicts_in_changes(
+    device: &WgDevice,
+    conf: &WgDeviceSetter,
+) -> Vec<AllowedIpConflict> {
+    let mut conflicts = Vec::new();
+
+    let before = owners(
+        device
+            .peers
+            .iter()
+            .map(|peer| (&peer.public_key, &peer.allow_ips)),
+    );
+
+    // Apply the changes the same way Wireguard does
+    let mut after: Vec<(PublicKey, Vec<IpNetwork>)> = if conf.replace_peers {
+        Vec::new()
+    } else {
+        device
+            .peers
+            .iter()
+            .map(|peer| (peer.public_key.clone(), canonical_all(&peer.allow_ips)))
+            .collect()
+    };
+    let mut current: HashMap<IpNetwork, PublicKey> = after
+        .iter()
+        .flat_map(|(key, ips)| ips.iter().map(move |ip| (*ip, key.clone())))
+        .collect();
+    let mut assigned: HashMap<IpNetwork, Vec<PublicKey>> = HashMap::new();
+
+    for setter in &conf.peers {
+        let position = after.iter().position(|(key, _)| key == &setter.pubkey);
+        if setter.remove {
+            if let Some(position) = position {
+                let (_, ips) = after.remove(position);
+                release(&mut current, &ips);
+            }
+            continue;
+        }
+        let position = match position {
+            Some(position) => position,
+            None if setter.update_only => continue,
+            None => {
+                after.push((setter.pubkey.clone(), Vec::new()));
+                after.len() - 1
+            }
+        };
+        if setter.replace_allowed_ips {
+            let ips = std::mem::take(&mut after[position].1);
+            release(&mut current, &ips);
+        }
+
+        for allowed_ip in canonical_all(&setter.allowed_ips) {
+            let peers = assigned.entry(allowed_ip).or_default();
+            if !peers.contains(&setter.pubkey) {
+                peers.push(setter.pubkey.clone());
+            }
+            match current.insert(allowed_ip, setter.pubkey.clone()) {
+                Some(previous) if previous == setter.pubkey => continue,
+                Some(previous) => {
+                    if let Some((_, ips)) = after.iter_mut().find(|(key, _)| key == &previous) {
+                        ips.retain(|ip| ip != &allowed_ip);
+                    }
+                }
+                None => (),
+            }
+            after[position].1.push(allowed_ip);
+        }
+    }
+
+    let mut duplicates: Vec<_> = assigned
+        .into_iter()
+        .filter(|(_, peers)| peers.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(allowed_ip, _)| *allowed_ip);
+    for (allowed_ip, peers) in duplicates {
+        conflicts.push(AllowedIpConflict::Duplicate { allowed_ip, peers });
+    }
+
+    for (public_key, ips) in &after {
+        for allowed_ip in ips {
+            match before.get(allowed_ip) {
+                Some(&from) if from != public_key => conflicts.push(AllowedIpConflict::Moved {
+                    allowed_ip: *allowed_ip,
+                    from: from.clone(),
+                    to: public_key.clone(),
+                }),
+                _ => (),
+            }
+        }
+    }
+
+    conflicts.extend(find_overlaps(&after));
+    conflicts
+}
+
+fn find_duplicates(assignments: &[(PublicKey, Vec<IpNetwork>)]) -> Vec<AllowedIpConflict> {
+    let mut seen: HashMap<IpNetwork, Vec<PublicKey>> = HashMap::new();
+    for (public_key, ips) in assignments {
+        for allowed_ip in ips {
+            let peers = seen.entry(*allowed_ip).or_default();
+            if !peers.contains(public_key) {
+                peers.push(public_key.clone());
+            }
+        }
+    }
+
+    let mut duplicates: Vec<_> = seen
+        .into_iter()
+        .filter(|(_, peers)| peers.len() > 1)
+        .collect();
+    duplicates.sort_by_key(|(allowed_ip, _)| *allowed_ip);
+    duplicates
+        .into_iter()
+        .map(|(allowed_ip, peers)| AllowedIpConflict::Duplicate { allowed_ip, peers })
+        .collect()
+}
+
+/// Look up the prefixes covering each allowed IP in the table of all peers
+fn find_overlaps(assignments: &[(PublicKey, Vec<IpNetwork>)]) -> Vec<AllowedIpConflict> {
+    let mut device = WgDevice::new("");
+    device.peers = assignments
+        .iter()
+        .map(|(public_key, ips)| {
+            let mut peer = Peer::new(public_key.clone());
+            peer.allow_ips = ips.clone();
+            peer
+        })
+        .collect();
+    let table = AllowedIpsTable::new(&device);
+
+    let mut conflicts = Vec::new();
+    for (peer, ips) in assignments {
+        for allowed_ip in ips {
+            for (covering, other) in table.covering(allowed_ip) {
+                if &other.public_key != peer {
+                    conflicts.push(AllowedIpConflict::Overlap {
+                        allowed_ip: *allowed_ip,
+                        peer: peer.clone(),
+                        covering,
+                        other: other.public_key.clone(),
+                    });
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Forget the owner of `ips`
+fn release(current: &mut HashMap<IpNetwork, PublicKey>, ips: &[IpNetwork]) {
+    for allowed_ip in ips {
+        current.remove(allowed_ip);
+    }
+}
+
+/// The last peer of each prefix
+fn owners<'a, I>(assignments: I) -> HashMap<IpNetwork, &'a PublicKey>
+where
+    I: Iterator<Item = (&'a PublicKey, &'a Vec<IpNetwork>)>,
+{
+    let mut owners = HashMap::new();
+    for (public_key, ips) in assignments {
+        for allowed_ip in canonical_all(ips) {
+            owners.insert(allowed_ip, public_key);
+        }
+    }
+    owners
+}
+
+fn canonical_all(ips: &[IpNetwork]) -> Vec<IpNetwork> {
+    ips.iter().map(normalize).collect()
+}
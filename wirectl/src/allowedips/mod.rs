@@ -0,0 +1,180 @@
+//! Allowed IPs analysis
+//!
+//! [`AllowedIpsTable`] resolves addresses to peers the same way Wireguard does,
+//! by longest-prefix match across the allowed IPs of every peer.
+//! [`find_conflicts()`] and [`find_conflicts_in_changes()`] report allowed IPs
+//! which are assigned to several peers, and [`exclude()`], [`aggregate()`] and
+//! [`normalize()`] do the arithmetic for building lists of allowed IPs.
+use crate::types::*;
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+mod conflict;
+mod ops;
+
+pub use conflict::*;
+pub use ops::*;
+
+/// An allowed IP which never takes effect, because a later peer has the same one
+///
+/// Wireguard moves an allowed IP to the last peer it is assigned to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShadowedEntry {
+    pub allowed_ip: IpNetwork,
+    /// The peer which loses the allowed IP
+    pub shadowed: PublicKey,
+    /// The peer which gets the allowed IP
+    pub by: PublicKey,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: [Option<Box<Node>>; 2],
+    peer: Option<usize>,
+}
+
+/// Longest-prefix-match table of the allowed IPs of a device
+///
+/// Lookups take at most one step per bit of the address.
+#[derive(Debug)]
+pub struct AllowedIpsTable<'a> {
+    peers: &'a [Peer],
+    v4: Node,
+    v6: Node,
+    len: usize,
+    shadowed: Vec<ShadowedEntry>,
+}
+
+impl<'a> AllowedIpsTable<'a> {
+    pub fn new(device: &'a WgDevice) -> Self {
+        let mut table = Self {
+            peers: &device.peers,
+            v4: Node::default(),
+            v6: Node::default(),
+            len: 0,
+            shadowed: Vec::new(),
+        };
+
+        for (index, peer) in device.peers.iter().enumerate() {
+            for allowed_ip in &peer.allow_ips {
+                let (bits, prefix) = key_of(allowed_ip);
+                let root = table.root_mut(allowed_ip.is_ipv4());
+                match insert(root, bits, prefix, index) {
+                    Some(previous) if previous != index => {
+                        table.shadowed.push(ShadowedEntry {
+                            allowed_ip: *allowed_ip,
+                            shadowed: device.peers[previous].public_key.clone(),
+                            by: peer.public_key.clone(),
+                        });
+                    }
+                    Some(_) => (),
+                    None => table.len += 1,
+                }
+            }
+        }
+
+        table
+    }
+
+    /// The peer which packets to `addr` are sent to
+    pub fn lookup(&self, addr: IpAddr) -> Option<&'a Peer> {
+        let (root, bits, width) = match addr {
+            IpAddr::V4(addr) => (&self.v4, (u32::from(addr) as u128) << 96, 32),
+            IpAddr::V6(addr) => (&self.v6, u128::from(addr), 128),
+        };
+
+        let mut node = root;
+        let mut found = node.peer;
+        for depth in 0..width {
+            match &node.children[bit_at(bits, depth)] {
+                Some(child) => node = child,
+                None => break,
+            }
+            if node.peer.is_some() {
+                found = node.peer;
+            }
+        }
+        found.map(|index| &self.peers[index])
+    }
+
+    /// The shorter prefixes containing `net`, and their peers, from the shortest
+    pub(crate) fn covering(&self, net: &IpNetwork) -> Vec<(IpNetwork, &'a Peer)> {
+        let (bits, prefix) = key_of(net);
+        let mut node = if net.is_ipv4() { &self.v4 } else { &self.v6 };
+        let mut found = Vec::new();
+        for depth in 0..prefix {
+            if let Some(index) = node.peer {
+                found.push((network_of(bits, depth, net.is_ipv4()), &self.peers[index]));
+            }
+            match &node.children[bit_at(bits, depth)] {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        found
+    }
+
+    /// Allowed IPs which are overridden by the same allowed IP of a later peer
+    pub fn shadowed(&self) -> &[ShadowedEntry] {
+        &self.shadowed
+    }
+
+    /// Number of distinct prefixes in the table
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn root_mut(&mut self, ipv4: bool) -> &mut Node {
+        if ipv4 {
+            &mut self.v4
+        } else {
+            &mut self.v6
+        }
+    }
+}
+
+/// The network bits of `net`, left-aligned in a `u128`, and its prefix length
+///
+/// Host bits are ignored, like Wireguard does.
+fn key_of(net: &IpNetwork) -> (u128, u8) {
+    let bits = match net.network() {
+        IpAddr::V4(addr) => (u32::from(addr) as u128) << 96,
+        IpAddr::V6(addr) => u128::from(addr),
+    };
+    (bits, net.prefix())
+}
+
+/// The prefix of length `prefix` of the left-aligned `bits`
+fn network_of(bits: u128, prefix: u8, ipv4: bool) -> IpNetwork {
+    let mask = match prefix {
+        0 => 0,
+        prefix => u128::MAX << (128 - prefix),
+    };
+    let bits = bits & mask;
+    let addr = if ipv4 {
+        IpAddr::V4(Ipv4Addr::from((bits >> 96) as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(bits))
+    };
+    IpNetwork::new(addr, prefix).expect("prefix is within the address width")
+}
+
+fn bit_at(bits: u128, depth: u8) -> usize {
+    ((bits >> (127 - depth)) & 1) as usize
+}
+
+/// Insert a prefix, returning the peer it previously belonged to
+fn insert(root: &mut Node, bits: u128, prefix: u8, peer: usize) -> Option<usize> {
+    let mut node = root;
+    for depth in 0..prefix {
+        node = node.children[bit_at(bits, depth)].get_or_insert_with(Default::default);
+    }
+    node.peer.replace(peer)
+}
+
+#[cfg(test)]
+mod tests;
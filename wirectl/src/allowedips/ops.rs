@@ -0,0 +1,153 @@
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// An inclusive range of addresses of a single family
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Range {
+    ipv6: bool,
+    start: u128,
+    end: u128,
+}
+
+impl Range {
+    fn new(net: &IpNetwork) -> Self {
+        let (ipv6, width, start) = match net.network() {
+            IpAddr::V4(addr) => (false, 32, u32::from(addr) as u128),
+            IpAddr::V6(addr) => (true, 128, u128::from(addr)),
+        };
+        Self {
+            ipv6,
+            start,
+            end: start | host_mask(width - net.prefix()),
+        }
+    }
+
+    fn width(&self) -> u8 {
+        if self.ipv6 {
+            128
+        } else {
+            32
+        }
+    }
+
+    /// The smallest list of networks covering the range exactly
+    fn to_networks(self, networks: &mut Vec<IpNetwork>) {
+        let width = self.width();
+        let mut start = self.start;
+        loop {
+            // The largest aligned block starting at `start` which fits the range
+            let mut host_bits = (start.trailing_zeros() as u8).min(width);
+            while start + host_mask(host_bits) > self.end {
+                host_bits -= 1;
+            }
+
+            let addr: IpAddr = if self.ipv6 {
+                Ipv6Addr::from(start).into()
+            } else {
+                Ipv4Addr::from(start as u32).into()
+            };
+            // The prefix is never longer than the address
+            networks.push(IpNetwork::new(addr, width - host_bits).unwrap());
+
+            match (start + host_mask(host_bits)).checked_add(1) {
+                Some(next) if next <= self.end => start = next,
+                _ => break,
+            }
+        }
+    }
+}
+
+fn host_mask(host_bits: u8) -> u128 {
+    if host_bits >= 128 {
+        u128::MAX
+    } else {
+        (1 << host_bits) - 1
+    }
+}
+
+/// Sorted, non-overlapping and non-adjacent ranges
+fn merged_ranges(networks: &[IpNetwork]) -> Vec<Range> {
+    let mut ranges: Vec<_> = networks.iter().map(Range::new).collect();
+    ranges.sort();
+
+    let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.ipv6 == range.ipv6 && range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn to_networks(ranges: Vec<Range>) -> Vec<IpNetwork> {
+    let mut networks = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        range.to_networks(&mut networks);
+    }
+    networks
+}
+
+/// Clear the host bits of `network`
+///
+/// `10.0.0.1/24` becomes `10.0.0.0/24`, which is what Wireguard stores.
+pub fn normalize(network: &IpNetwork) -> IpNetwork {
+    // The network address always fits the prefix
+    IpNetwork::new(network.network(), network.prefix()).unwrap()
+}
+
+/// Merge overlapping and adjacent networks into the smallest equivalent list
+///
+/// The result is sorted, with IPv4 networks first.
+pub fn aggregate(networks: &[IpNetwork]) -> Vec<IpNetwork> {
+    to_networks(merged_ranges(networks))
+}
+
+/// Remove the addresses of `excluded` from `networks`
+///
+/// The result is aggregated like [`aggregate()`].
+///
+/// ```
+/// use wirectl::allowedips::exclude;
+///
+/// let everything = ["0.0.0.0/0".parse().unwrap()];
+/// let private = [
+///     "10.0.0.0/8".parse().unwrap(),
+///     "172.16.0.0/12".parse().unwrap(),
+///     "192.168.0.0/16".parse().unwrap(),
+/// ];
+/// let allowed_ips = exclude(&everything, &private);
+/// assert_eq!(allowed_ips.len(), 31);
+/// assert_eq!(allowed_ips[0], "0.0.0.0/5".parse().unwrap());
+/// ```
+pub fn exclude(networks: &[IpNetwork], excluded: &[IpNetwork]) -> Vec<IpNetwork> {
+    let excluded = merged_ranges(excluded);
+    let mut remaining = Vec::new();
+
+    for mut range in merged_ranges(networks) {
+        let mut empty = false;
+        for cut in excluded.iter().filter(|cut| cut.ipv6 == range.ipv6) {
+            if cut.end < range.start || cut.start > range.end {
+                continue;
+            }
+            if cut.start > range.start {
+                remaining.push(Range {
+                    end: cut.start - 1,
+                    ..range
+                });
+            }
+            if cut.end >= range.end {
+                empty = true;
+                break;
+            }
+            range.start = cut.end + 1;
+        }
+        if !empty {
+            remaining.push(range);
+        }
+    }
+
+    to_networks(remaining)
+}
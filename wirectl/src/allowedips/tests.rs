@@ -0,0 +1,267 @@
+use super::*;
+
+fn key(n: u8) -> PublicKey {
+    PublicKey::from([n; WG_KEY_LEN])
+}
+
+fn peer(pubkey: u8, allowed_ips: &[&str]) -> Peer {
+    let mut peer = Peer::new(key(pubkey));
+    peer.allow_ips = allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect();
+    peer
+}
+
+fn device(peers: Vec<Peer>) -> WgDevice {
+    let mut device = WgDevice::new("test");
+    device.peers = peers;
+    device
+}
+
+fn lookup(table: &AllowedIpsTable, addr: &str) -> Option<PublicKey> {
+    table
+        .lookup(addr.parse().unwrap())
+        .map(|peer| peer.public_key.clone())
+}
+
+#[test]
+fn table_longest_prefix_match() {
+    let device = device(vec![
+        peer(1, &["0.0.0.0/0", "::/0"]),
+        peer(2, &["10.0.0.0/8", "fd00::/8"]),
+        peer(3, &["10.1.0.0/16", "10.1.2.3/32"]),
+        peer(4, &["fd00:1::/64"]),
+    ]);
+    let table = AllowedIpsTable::new(&device);
+
+    assert_eq!(table.len(), 7);
+    assert_eq!(lookup(&table, "192.0.2.1"), Some(key(1)));
+    assert_eq!(lookup(&table, "10.2.0.1"), Some(key(2)));
+    assert_eq!(lookup(&table, "10.1.0.1"), Some(key(3)));
+    assert_eq!(lookup(&table, "10.1.2.3"), Some(key(3)));
+    assert_eq!(lookup(&table, "2001:db8::1"), Some(key(1)));
+    assert_eq!(lookup(&table, "fd00:2::1"), Some(key(2)));
+    assert_eq!(lookup(&table, "fd00:1::1"), Some(key(4)));
+    assert!(table.shadowed().is_empty());
+}
+
+#[test]
+fn table_covering() {
+    let device = device(vec![
+        peer(1, &["0.0.0.0/0", "::/0"]),
+        peer(2, &["10.0.0.0/8", "fd00::/8"]),
+        peer(3, &["10.1.0.0/16"]),
+    ]);
+    let table = AllowedIpsTable::new(&device);
+    let covering = |net: &str| -> Vec<(String, PublicKey)> {
+        table
+            .covering(&net.parse().unwrap())
+            .into_iter()
+            .map(|(net, peer)| (net.to_string(), peer.public_key.clone()))
+            .collect()
+    };
+
+    assert_eq!(
+        covering("10.1.2.0/24"),
+        vec![
+            ("0.0.0.0/0".to_owned(), key(1)),
+            ("10.0.0.0/8".to_owned(), key(2)),
+            ("10.1.0.0/16".to_owned(), key(3)),
+        ]
+    );
+    // Only strictly shorter prefixes cover a prefix
+    assert_eq!(
+        covering("10.1.0.0/16"),
+        vec![
+            ("0.0.0.0/0".to_owned(), key(1)),
+            ("10.0.0.0/8".to_owned(), key(2)),
+        ]
+    );
+    assert_eq!(
+        covering("fd00:1::/64"),
+        vec![("::/0".to_owned(), key(1)), ("fd00::/8".to_owned(), key(2))]
+    );
+    assert!(covering("0.0.0.0/0").is_empty());
+}
+
+#[test]
+fn table_families_are_separate() {
+    let device = device(vec![peer(1, &["0.0.0.0/0"])]);
+    let table = AllowedIpsTable::new(&device);
+
+    assert_eq!(lookup(&table, "10.0.0.1"), Some(key(1)));
+    assert_eq!(lookup(&table, "::ffff:10.0.0.1"), None);
+    assert_eq!(lookup(&table, "::"), None);
+}
+
+#[test]
+fn table_shadowed() {
+    let device = device(vec![
+        peer(1, &["10.0.0.2/32", "10.0.1.0/24"]),
+        // Host bits are ignored, so this is 10.0.1.0/24 as well
+        peer(2, &["10.0.0.2/32", "10.0.1.1/24"]),
+    ]);
+    let table = AllowedIpsTable::new(&device);
+
+    assert_eq!(table.len(), 2);
+    assert_eq!(lookup(&table, "10.0.0.2"), Some(key(2)));
+    assert_eq!(lookup(&table, "10.0.1.7"), Some(key(2)));
+    assert_eq!(
+        table.shadowed(),
+        &[
+            ShadowedEntry {
+                allowed_ip: "10.0.0.2/32".parse().unwrap(),
+                shadowed: key(1),
+                by: key(2),
+            },
+            ShadowedEntry {
+                allowed_ip: "10.0.1.1/24".parse().unwrap(),
+                shadowed: key(1),
+                by: key(2),
+            },
+        ]
+    );
+}
+
+#[test]
+fn conflicts_in_device() {
+    let device = device(vec![
+        peer(1, &["10.0.0.0/24", "10.0.1.1/32"]),
+        peer(2, &["10.0.0.7/32", "10.0.1.1/32"]),
+        // Overlaps within one peer are fine
+        peer(3, &["10.9.0.0/16", "10.9.1.0/24"]),
+    ]);
+
+    assert_eq!(
+        find_conflicts(&device.peers),
+        vec![
+            AllowedIpConflict::Duplicate {
+                allowed_ip: "10.0.1.1/32".parse().unwrap(),
+                peers: vec![key(1), key(2)],
+            },
+            AllowedIpConflict::Overlap {
+                allowed_ip: "10.0.0.7/32".parse().unwrap(),
+                peer: key(2),
+                covering: "10.0.0.0/24".parse().unwrap(),
+                other: key(1),
+            },
+        ]
+    );
+}
+
+#[test]
+fn conflicts_in_changes() {
+    let device = device(vec![
+        peer(1, &["10.0.0.1/32"]),
+        peer(2, &["10.0.0.2/32"]),
+        peer(3, &["10.0.0.3/32"]),
+    ]);
+    let net = |s: &str| -> IpNetwork { s.parse().unwrap() };
+    let conf = WgDeviceSetter::from(&device)
+        // Moves 10.0.0.1/32 from peer 1
+        .set_peer(PeerSetter::new(key(2)).add_allowed_ip(net("10.0.0.1/32")))
+        // Assigned twice within the changes
+        .set_peer(PeerSetter::new(key(4)).add_allowed_ip(net("10.1.0.1/32")))
+        .set_peer(
+            PeerSetter::new(key(5))
+                .add_allowed_ip(net("10.1.0.1/32"))
+                .add_allowed_ip(net("10.0.0.0/24")),
+        )
+        // Removed peers don't conflict anymore
+        .set_peer(PeerSetter::new(key(3)).set_remove());
+
+    let conflicts = find_conflicts_in_changes(&device, &conf);
+    assert_eq!(
+        conflicts,
+        vec![
+            AllowedIpConflict::Duplicate {
+                allowed_ip: net("10.1.0.1/32"),
+                peers: vec![key(4), key(5)],
+            },
+            AllowedIpConflict::Moved {
+                allowed_ip: net("10.0.0.1/32"),
+                from: key(1),
+                to: key(2),
+            },
+            AllowedIpConflict::Overlap {
+                allowed_ip: net("10.0.0.2/32"),
+                peer: key(2),
+                covering: net("10.0.0.0/24"),
+                other: key(5),
+            },
+            AllowedIpConflict::Overlap {
+                allowed_ip: net("10.0.0.1/32"),
+                peer: key(2),
+                covering: net("10.0.0.0/24"),
+                other: key(5),
+            },
+        ]
+    );
+}
+
+fn nets(networks: &[&str]) -> Vec<IpNetwork> {
+    networks.iter().map(|net| net.parse().unwrap()).collect()
+}
+
+#[test]
+fn ops_normalize() {
+    assert_eq!(
+        normalize(&"10.1.2.3/16".parse().unwrap()),
+        "10.1.0.0/16".parse::<IpNetwork>().unwrap()
+    );
+    assert_eq!(
+        normalize(&"fd00::1/64".parse().unwrap()),
+        "fd00::/64".parse::<IpNetwork>().unwrap()
+    );
+}
+
+#[test]
+fn ops_aggregate() {
+    assert_eq!(
+        aggregate(&nets(&[
+            "fd00::/65",
+            "10.0.1.0/24",
+            "10.0.0.7/24",
+            "fd00:0:0:0:8000::/65",
+            "10.0.0.5/32",
+            "10.0.3.0/24",
+        ])),
+        nets(&["10.0.0.0/23", "10.0.3.0/24", "fd00::/64"])
+    );
+    assert_eq!(
+        aggregate(&nets(&["0.0.0.0/1", "128.0.0.0/1", "::/0", "::1/128"])),
+        nets(&["0.0.0.0/0", "::/0"])
+    );
+}
+
+#[test]
+fn ops_exclude() {
+    assert_eq!(
+        exclude(
+            &nets(&["10.0.0.0/24"]),
+            &nets(&["10.0.0.0/26", "10.0.0.192/26"])
+        ),
+        nets(&["10.0.0.64/26", "10.0.0.128/26"])
+    );
+    assert_eq!(
+        exclude(&nets(&["10.0.0.0/24"]), &nets(&["10.0.0.5/32"])),
+        nets(&[
+            "10.0.0.0/30",
+            "10.0.0.4/32",
+            "10.0.0.6/31",
+            "10.0.0.8/29",
+            "10.0.0.16/28",
+            "10.0.0.32/27",
+            "10.0.0.64/26",
+            "10.0.0.128/25",
+        ])
+    );
+    // Other families and disjoint networks are untouched
+    assert_eq!(
+        exclude(&nets(&["::/0", "10.0.0.0/8"]), &nets(&["0.0.0.0/0"])),
+        nets(&["::/0"])
+    );
+    assert_eq!(
+        exclude(&nets(&["::/0"]), &nets(&["2001:db8::1/128"])).len(),
+        128
+    );
+    assert!(exclude(&nets(&["10.0.0.0/8"]), &nets(&["0.0.0.0/0"])).is_empty());
+}
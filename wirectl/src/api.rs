@@ -1,8 +1,6 @@
+use crate::timeout::{with_timeout, TimeoutPhase, Timeouts};
 use crate::WireCtlError;
-use crate::{ipc, types::*};
-use futures::TryStreamExt;
-use rtnetlink::new_connection_with_socket;
-use rtnetlink::sys::SmolSocket;
+use crate::{ipc, netlink, netns::NetNs, types::*};
 
 cfg_if! {
     if #[cfg(target_os = "linux")] {
@@ -26,9 +24,12 @@ pub enum WgApi {
 }
 
 impl WgApi {
-    pub(crate) async fn list_interfaces(self) -> Result<Vec<String>, WireCtlError> {
+    pub(crate) async fn list_interfaces(
+        self,
+        timeouts: &Timeouts,
+    ) -> Result<Vec<String>, WireCtlError> {
         match self {
-            WgApi::IPC => ipc::list_interfaces().await,
+            WgApi::IPC => ipc::list_interfaces(timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -36,9 +37,14 @@ impl WgApi {
         }
     }
 
-    pub(crate) async fn check_interface(self, ifname: &str) -> Result<(), WireCtlError> {
+    pub(crate) async fn check_interface(
+        self,
+        ifname: &str,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
+    ) -> Result<(), WireCtlError> {
         match self {
-            WgApi::IPC => ipc::check_device(ifname).await,
+            WgApi::IPC => ipc::check_device(ifname, netns, timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -46,9 +52,14 @@ impl WgApi {
         }
     }
 
-    pub(crate) async fn get_config(self, ifname: &str) -> Result<WgDevice, WireCtlError> {
+    pub(crate) async fn get_config(
+        self,
+        ifname: &str,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
+    ) -> Result<WgDevice, WireCtlError> {
         match self {
-            WgApi::IPC => ipc::get_config(ifname).await,
+            WgApi::IPC => ipc::get_config(ifname, netns, timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -60,9 +71,11 @@ impl WgApi {
         self,
         ifname: &str,
         conf: WgDeviceSetter,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
     ) -> Result<(), WireCtlError> {
         match self {
-            WgApi::IPC => ipc::set_config(ifname, conf).await,
+            WgApi::IPC => ipc::set_config(ifname, conf, netns, timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -70,9 +83,14 @@ impl WgApi {
         }
     }
 
-    pub(crate) async fn add_interface(self, ifname: &str) -> Result<(), WireCtlError> {
+    pub(crate) async fn add_interface(
+        self,
+        ifname: &str,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
+    ) -> Result<(), WireCtlError> {
         match self {
-            WgApi::IPC => ipc::create_interface(ifname).await,
+            WgApi::IPC => ipc::create_interface(ifname, netns, timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -80,9 +98,14 @@ impl WgApi {
         }
     }
 
-    pub(crate) async fn del_interface(self, ifname: &str) -> Result<(), WireCtlError> {
+    pub(crate) async fn del_interface(
+        self,
+        ifname: &str,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
+    ) -> Result<(), WireCtlError> {
         let is_wg_if = match self {
-            WgApi::IPC => ipc::check_device(ifname).await,
+            WgApi::IPC => ipc::check_device(ifname, netns, timeouts).await,
             #[cfg(target_os = "linux")]
             WgApi::Linux => todo!(),
             #[cfg(any(target_os = "openbsd", target_os = "freebsd"))]
@@ -92,16 +115,11 @@ impl WgApi {
             return Err(WireCtlError::NotFound);
         }
 
-        let (connection, handle, _) = new_connection_with_socket::<SmolSocket>()?;
-        smol::spawn(connection).detach();
-
-        let mut links = handle.link().get().match_name(ifname.to_owned()).execute();
-
-        if let Some(msg) = links.try_next().await? {
-            handle.link().del(msg.header.index).execute().await?;
-        } else {
-            return Err(WireCtlError::NotFound);
-        }
-        Ok(())
+        let handle = netlink::connect(netns)?;
+        let index = netlink::link_index(&handle, ifname, timeouts).await?;
+        with_timeout(timeouts, TimeoutPhase::Write, async {
+            Ok(handle.link().del(index).execute().await?)
+        })
+        .await
     }
 }
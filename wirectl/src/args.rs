@@ -1,4 +1,8 @@
-use clap::{Arg, Args, Error, ErrorKind, FromArgMatches, Parser, Subcommand};
+use clap::{
+    error::ErrorKind, Arg, ArgAction, Args, Command, Error, FromArgMatches, Parser, Subcommand,
+};
+use ipnetwork::IpNetwork;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -21,6 +25,8 @@ pub enum SubCommands {
     Genpsk,
     /// Reads a private key from stdin and writes a public key to stdout
     Pubkey,
+    /// Serves the statistics of every interface as Prometheus metrics
+    Exporter(ExporterCmd),
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -28,6 +34,10 @@ pub struct ShowCmd {
     /// Interface name to show, or specify "all" to print every interface found
     pub interface: Option<String>,
     pub fields: Option<ShowFields>,
+    /// Print the configuration as JSON
+    pub json: bool,
+    /// Print the transfer rates of the peers at this interval
+    pub watch: Option<Duration>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -72,8 +82,8 @@ impl ShowFields {
 }
 
 impl Args for ShowCmd {
-    fn augment_args(app: clap::App<'_>) -> clap::App<'_> {
-        app.arg(
+    fn augment_args(cmd: Command) -> Command {
+        cmd.arg(
             Arg::new("interface")
                 .help("Interface name to show, or specify \"all\" to print every interface found")
                 .index(1)
@@ -83,9 +93,8 @@ impl Args for ShowCmd {
             Arg::new("fields")
                 .help("Specifying the fields to print")
                 .index(2)
-                .takes_value(true)
-                .multiple_values(true)
-                .possible_values(&[
+                .num_args(1..)
+                .value_parser([
                     "public-key",
                     "private-key",
                     "listen-port",
@@ -100,10 +109,25 @@ impl Args for ShowCmd {
                     "dump",
                 ]),
         )
+        .arg(
+            Arg::new("json")
+                .help("Print the whole configuration as JSON")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("fields"),
+        )
+        .arg(
+            Arg::new("watch")
+                .help("Print the transfer rates of the peers every <SECONDS>")
+                .long("watch")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["fields", "json"]),
+        )
     }
 
-    fn augment_args_for_update(app: clap::App<'_>) -> clap::App<'_> {
-        Self::augment_args(app)
+    fn augment_args_for_update(cmd: Command) -> Command {
+        Self::augment_args(cmd)
     }
 }
 
@@ -115,7 +139,7 @@ impl FromArgMatches for ShowCmd {
     }
 
     fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), Error> {
-        if let Some(ifname) = matches.value_of("interface") {
+        if let Some(ifname) = matches.get_one::<String>("interface") {
             self.interface = if ifname != "all" {
                 Some(ifname.to_owned())
             } else {
@@ -125,11 +149,11 @@ impl FromArgMatches for ShowCmd {
             self.interface = None;
         }
 
-        if let Some(match_fields) = matches.values_of("fields") {
+        if let Some(match_fields) = matches.get_many::<String>("fields") {
             let mut fields = ShowFields::default();
 
             for f in match_fields {
-                match f {
+                match f.as_str() {
                     "public-key" => fields.public_key = true,
                     "private-key" => fields.private_key = true,
                     "listen-port" => fields.listen_port = true,
@@ -150,11 +174,179 @@ impl FromArgMatches for ShowCmd {
             self.fields = None;
         }
 
+        self.json = matches.get_flag("json");
+        self.watch = matches
+            .get_one::<u64>("watch")
+            .map(|&secs| Duration::from_secs(secs));
+
         Ok(())
     }
 }
 
-#[derive(Debug, Args)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct SetCmd {
-    interface: String,
+    pub interface: String,
+    pub private_key: Option<PathBuf>,
+    pub listen_port: Option<u16>,
+    /// `Some(0)` turns the fwmark off
+    pub fwmark: Option<u32>,
+    pub peers: Vec<SetPeer>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetPeer {
+    pub public_key: String,
+    pub remove: bool,
+    pub preshared_key: Option<PathBuf>,
+    pub endpoint: Option<String>,
+    /// `Some(0)` turns persistent keepalive off
+    pub persistent_keepalive: Option<u16>,
+    /// Replaces the allowed IPs of the peer
+    pub allowed_ips: Option<Vec<IpNetwork>>,
+}
+
+impl Args for SetCmd {
+    fn augment_args(cmd: Command) -> Command {
+        cmd.arg(
+            Arg::new("interface")
+                .help("Interface name to change")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::new("options")
+                .help(
+                    "[listen-port <port>] [fwmark <mark>] [private-key <file path>] \
+                     [peer <base64 public key> [remove] [preshared-key <file path>] \
+                     [endpoint <ip>:<port>] [persistent-keepalive <interval seconds>] \
+                     [allowed-ips <ip1>/<cidr1>[,<ip2>/<cidr2>]...] ]...",
+                )
+                .index(2)
+                .num_args(0..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true),
+        )
+    }
+
+    fn augment_args_for_update(cmd: Command) -> Command {
+        Self::augment_args(cmd)
+    }
+}
+
+impl FromArgMatches for SetCmd {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, Error> {
+        let mut arg = Self::default();
+        arg.update_from_arg_matches(matches)?;
+        Ok(arg)
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), Error> {
+        *self = Self::default();
+        if let Some(ifname) = matches.get_one::<String>("interface") {
+            self.interface = ifname.to_owned();
+        }
+
+        let mut options = matches
+            .get_many::<String>("options")
+            .into_iter()
+            .flatten()
+            .map(String::as_str);
+        while let Some(option) = options.next() {
+            if let Some(peer) = self.peers.last_mut() {
+                match option {
+                    "remove" => {
+                        peer.remove = true;
+                        continue;
+                    }
+                    "preshared-key" => {
+                        peer.preshared_key = Some(value_of(option, options.next())?.into());
+                        continue;
+                    }
+                    "endpoint" => {
+                        peer.endpoint = Some(value_of(option, options.next())?.to_owned());
+                        continue;
+                    }
+                    "persistent-keepalive" => {
+                        let value = value_of(option, options.next())?;
+                        peer.persistent_keepalive = Some(parse_off(option, value)?);
+                        continue;
+                    }
+                    "allowed-ips" => {
+                        let value = value_of(option, options.next())?;
+                        let allowed_ips = value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|ip| !ip.is_empty())
+                            .map(|ip| parse_value(option, ip))
+                            .collect::<Result<_, _>>()?;
+                        peer.allowed_ips = Some(allowed_ips);
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+
+            match option {
+                "listen-port" => {
+                    self.listen_port = Some(parse_value(option, value_of(option, options.next())?)?)
+                }
+                "fwmark" => {
+                    self.fwmark = Some(parse_off(option, value_of(option, options.next())?)?)
+                }
+                "private-key" => self.private_key = Some(value_of(option, options.next())?.into()),
+                "peer" => self.peers.push(SetPeer {
+                    public_key: value_of(option, options.next())?.to_owned(),
+                    ..Default::default()
+                }),
+                _ => {
+                    return Err(Error::raw(
+                        ErrorKind::InvalidValue,
+                        format!("Invalid argument: {}", option),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn value_of<'a>(option: &str, value: Option<&'a str>) -> Result<&'a str, Error> {
+    value.ok_or_else(|| {
+        Error::raw(
+            ErrorKind::InvalidValue,
+            format!("Missing value of {}", option),
+        )
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| {
+        Error::raw(
+            ErrorKind::InvalidValue,
+            format!("Invalid value of {}: {}", option, value),
+        )
+    })
 }
+
+/// Parse a number, where `off` means `0`
+fn parse_off<T: std::str::FromStr + Default>(option: &str, value: &str) -> Result<T, Error> {
+    if value == "off" {
+        Ok(T::default())
+    } else {
+        parse_value(option, value)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExporterCmd {
+    /// Address to serve the `/metrics` endpoint on
+    #[clap(long, default_value = "127.0.0.1:9586")]
+    pub listen: SocketAddr,
+    /// File mapping peer public keys to friendly names, one "<public key> <name>" per line
+    #[clap(long)]
+    pub peer_names: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests;
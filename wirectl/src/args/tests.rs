@@ -0,0 +1,120 @@
+use super::*;
+
+fn parse_set(args: &[&str]) -> Result<SetCmd, Error> {
+    let opts = Opts::try_parse_from(["wirectl", "set"].iter().chain(args))?;
+    match opts.subcmd {
+        SubCommands::Set(cmd) => Ok(cmd),
+        subcmd => panic!("unexpected subcommand: {:?}", subcmd),
+    }
+}
+
+#[test]
+fn set_device_options() {
+    let cmd = parse_set(&[
+        "wg0",
+        "listen-port",
+        "51820",
+        "fwmark",
+        "off",
+        "private-key",
+        "/etc/wireguard/private",
+    ])
+    .unwrap();
+    assert_eq!(
+        cmd,
+        SetCmd {
+            interface: "wg0".to_owned(),
+            private_key: Some("/etc/wireguard/private".into()),
+            listen_port: Some(51820),
+            fwmark: Some(0),
+            peers: Vec::new(),
+        }
+    );
+}
+
+#[test]
+fn set_peer_options() {
+    let cmd = parse_set(&[
+        "wg0",
+        "peer",
+        "AAAA",
+        "endpoint",
+        "vpn.example.com:51820",
+        "persistent-keepalive",
+        "off",
+        "allowed-ips",
+        "10.0.0.0/24, fd00::/64,",
+        "peer",
+        "BBBB",
+        "remove",
+        "listen-port",
+        "1234",
+        "peer",
+        "CCCC",
+        "preshared-key",
+        "/dev/null",
+        "allowed-ips",
+        "",
+    ])
+    .unwrap();
+    assert_eq!(cmd.listen_port, Some(1234));
+    assert_eq!(
+        cmd.peers,
+        vec![
+            SetPeer {
+                public_key: "AAAA".to_owned(),
+                endpoint: Some("vpn.example.com:51820".to_owned()),
+                persistent_keepalive: Some(0),
+                allowed_ips: Some(vec![
+                    "10.0.0.0/24".parse().unwrap(),
+                    "fd00::/64".parse().unwrap()
+                ]),
+                ..Default::default()
+            },
+            SetPeer {
+                public_key: "BBBB".to_owned(),
+                remove: true,
+                ..Default::default()
+            },
+            SetPeer {
+                public_key: "CCCC".to_owned(),
+                preshared_key: Some("/dev/null".into()),
+                allowed_ips: Some(Vec::new()),
+                ..Default::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn set_invalid_options() {
+    // Peer options are only valid after a peer
+    assert!(parse_set(&["wg0", "endpoint", "10.0.0.1:51820"]).is_err());
+    assert!(parse_set(&["wg0", "remove"]).is_err());
+    assert!(parse_set(&["wg0", "listen-port"]).is_err());
+    assert!(parse_set(&["wg0", "listen-port", "65536"]).is_err());
+    assert!(parse_set(&["wg0", "peer"]).is_err());
+    assert!(parse_set(&["wg0", "peer", "AAAA", "allowed-ips", "10.0.0.1/33"]).is_err());
+    assert!(parse_set(&["wg0", "unknown"]).is_err());
+    assert!(parse_set(&[]).is_err());
+}
+
+fn parse_show(args: &[&str]) -> Result<ShowCmd, Error> {
+    let opts = Opts::try_parse_from(["wirectl", "show"].iter().chain(args))?;
+    match opts.subcmd {
+        SubCommands::Show(cmd) => Ok(cmd),
+        subcmd => panic!("unexpected subcommand: {:?}", subcmd),
+    }
+}
+
+#[test]
+fn show_watch() {
+    let cmd = parse_show(&["wg0", "--watch", "2"]).unwrap();
+    assert_eq!(cmd.interface.as_deref(), Some("wg0"));
+    assert_eq!(cmd.watch, Some(Duration::from_secs(2)));
+    assert_eq!(parse_show(&[]).unwrap().watch, None);
+
+    assert!(parse_show(&["wg0", "--watch", "0"]).is_err());
+    assert!(parse_show(&["wg0", "--watch", "1", "--json"]).is_err());
+    assert!(parse_show(&["wg0", "transfer", "--watch", "1"]).is_err());
+}
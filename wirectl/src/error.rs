@@ -1,7 +1,9 @@
 use std::num::ParseIntError;
 
+use crate::timeout::TimeoutPhase;
+use crate::types::{PublicKey, ValidationErrors};
 use async_process::ExitStatus;
-use ipnetwork::IpNetworkError;
+use ipnetwork::{IpNetwork, IpNetworkError};
 use rtnetlink::Error as NlError;
 use std::net::AddrParseError;
 use thiserror::Error;
@@ -22,12 +24,24 @@ pub enum WireCtlError {
     InvalidProtocol,
     #[error("Invalid configuration")]
     InvalidConfig,
+    #[error("Invalid configuration: {0}")]
+    Validation(#[from] ValidationErrors),
     #[error("Interface not found")]
     NotFound,
+    #[error("Peer {} not found", .0.to_base64())]
+    PeerNotFound(PublicKey),
+    #[error("Peer {} already exists", .0.to_base64())]
+    PeerAlreadyExists(PublicKey),
+    #[error("Address {0} not found")]
+    AddressNotFound(IpNetwork),
+    #[error("Address {0} already exists")]
+    AddressAlreadyExists(IpNetwork),
     #[error("Device Error: {0}")]
     DeviceError(i32),
     #[error("Failed to launch userspace implementation. Exit status: {0}")]
     UserspaceLaunch(ExitStatus),
+    #[error("Operation timed out during {0} phase")]
+    Timeout(TimeoutPhase),
     #[error("Unknown Error")]
     Unknown,
 }
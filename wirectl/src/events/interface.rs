@@ -0,0 +1,394 @@
+use crate::{
+    ipc::{self, WG_SOCKET_PATH},
+    timeout::{default_timeouts, with_timeout, TimeoutPhase},
+    WireCtlError,
+};
+use futures::{channel::mpsc::UnboundedReceiver, Stream, StreamExt, TryStreamExt};
+use nix::{
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent},
+    unistd::close,
+};
+use rtnetlink::{
+    constants::RTMGRP_LINK,
+    new_connection_with_socket,
+    packet::{
+        nlas::link::{Info, InfoKind, Nla},
+        LinkMessage, NetlinkMessage, NetlinkPayload, RtnlMessage, IFF_UP,
+    },
+    sys::{AsyncSocket, SmolSocket, SocketAddr},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use smol::Async;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    os::unix::io::{AsRawFd, RawFd},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InterfaceEvent {
+    pub ifname: String,
+    pub kind: InterfaceEventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum InterfaceEventKind {
+    InterfaceAdded,
+    InterfaceRemoved,
+    /// The link was brought up administratively
+    LinkUp,
+    LinkDown,
+}
+
+/// A change reported by rtnetlink or by the socket directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    /// A link was created or changed
+    Link {
+        index: u32,
+        ifname: String,
+        wireguard: bool,
+        up: bool,
+    },
+    LinkRemoved {
+        index: u32,
+    },
+    /// The control socket of a userspace implementation was created or removed
+    Socket {
+        ifname: String,
+        present: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct LinkState {
+    ifname: String,
+    wireguard: bool,
+    up: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Presence {
+    present: bool,
+    up: bool,
+}
+
+/// Merges the changes of both sources into the state of each interface, so
+/// that an interface seen by both is only reported once
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InterfaceTracker {
+    links: HashMap<u32, LinkState>,
+    sockets: HashSet<String>,
+}
+
+impl InterfaceTracker {
+    /// An interface is either a kernel Wireguard link, or a link with the
+    /// socket of a userspace implementation
+    fn presence(&self, ifname: &str) -> Presence {
+        let link = self.links.values().find(|link| link.ifname == ifname);
+        let socket = self.sockets.contains(ifname);
+        Presence {
+            present: socket || link.is_some_and(|link| link.wireguard),
+            up: link.is_some_and(|link| link.up),
+        }
+    }
+
+    /// Record `change`, and return the events it caused
+    pub(crate) fn apply(&mut self, change: Change) -> Vec<InterfaceEvent> {
+        let mut affected = Vec::with_capacity(2);
+        match &change {
+            Change::Link { index, ifname, .. } => {
+                // A renamed link leaves its previous name
+                affected.extend(self.links.get(index).map(|link| link.ifname.clone()));
+                affected.push(ifname.clone());
+            }
+            Change::LinkRemoved { index } => {
+                affected.extend(self.links.get(index).map(|link| link.ifname.clone()));
+            }
+            Change::Socket { ifname, .. } => affected.push(ifname.clone()),
+        }
+        affected.dedup();
+        let before: Vec<_> = affected.iter().map(|name| self.presence(name)).collect();
+
+        match change {
+            Change::Link {
+                index,
+                ifname,
+                wireguard,
+                up,
+            } => {
+                self.links.insert(
+                    index,
+                    LinkState {
+                        ifname,
+                        wireguard,
+                        up,
+                    },
+                );
+            }
+            Change::LinkRemoved { index } => {
+                self.links.remove(&index);
+            }
+            Change::Socket { ifname, present } => {
+                if present {
+                    self.sockets.insert(ifname);
+                } else {
+                    self.sockets.remove(&ifname);
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        for (ifname, before) in affected.into_iter().zip(before) {
+            let after = self.presence(&ifname);
+            let mut push = |kind| {
+                events.push(InterfaceEvent {
+                    ifname: ifname.clone(),
+                    kind,
+                })
+            };
+            match (before.present, after.present) {
+                (false, true) => {
+                    push(InterfaceEventKind::InterfaceAdded);
+                    if after.up {
+                        push(InterfaceEventKind::LinkUp);
+                    }
+                }
+                (true, false) => push(InterfaceEventKind::InterfaceRemoved),
+                (true, true) if before.up != after.up => push(if after.up {
+                    InterfaceEventKind::LinkUp
+                } else {
+                    InterfaceEventKind::LinkDown
+                }),
+                _ => (),
+            }
+        }
+        events
+    }
+
+    /// Bring the sockets in line with `ifnames`, the ones currently in the
+    /// socket directory, and return the events it caused
+    pub(crate) fn sync_sockets(&mut self, ifnames: Vec<String>) -> Vec<InterfaceEvent> {
+        let removed: Vec<_> = self
+            .sockets
+            .iter()
+            .filter(|ifname| !ifnames.contains(ifname))
+            .cloned()
+            .collect();
+        let removed = removed.into_iter().map(|ifname| Change::Socket {
+            ifname,
+            present: false,
+        });
+        let added = ifnames.into_iter().map(|ifname| Change::Socket {
+            ifname,
+            present: true,
+        });
+        removed
+            .chain(added)
+            .flat_map(|change| self.apply(change))
+            .collect()
+    }
+}
+
+/// Closes the inotify instance, which nix leaves open
+#[derive(Debug)]
+struct InotifyFd(Inotify);
+
+impl AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Drop for InotifyFd {
+    fn drop(&mut self) {
+        close(self.0.as_raw_fd()).ok();
+    }
+}
+
+fn link_change(link: LinkMessage) -> Option<Change> {
+    let mut ifname = None;
+    let mut wireguard = false;
+    for nla in link.nlas {
+        match nla {
+            Nla::IfName(name) => ifname = Some(name),
+            Nla::Info(infos) => {
+                wireguard = infos
+                    .iter()
+                    .any(|info| matches!(info, Info::Kind(InfoKind::Wireguard)))
+            }
+            _ => (),
+        }
+    }
+    Some(Change::Link {
+        index: link.header.index,
+        ifname: ifname?,
+        wireguard,
+        up: link.header.flags & IFF_UP != 0,
+    })
+}
+
+fn message_change(message: NetlinkMessage<RtnlMessage>) -> Option<Change> {
+    match message.payload {
+        NetlinkPayload::InnerMessage(RtnlMessage::NewLink(link)) => link_change(link),
+        NetlinkPayload::InnerMessage(RtnlMessage::DelLink(link)) => Some(Change::LinkRemoved {
+            index: link.header.index,
+        }),
+        _ => None,
+    }
+}
+
+fn socket_change(event: InotifyEvent) -> Option<Change> {
+    let ifname = ipc::socket_ifname(event.name.as_ref()?)?;
+    let present = if event
+        .mask
+        .intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)
+    {
+        true
+    } else if event
+        .mask
+        .intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_MOVED_FROM)
+    {
+        false
+    } else {
+        return None;
+    };
+    Some(Change::Socket { ifname, present })
+}
+
+/// Watches the Wireguard interfaces appearing and disappearing, and produces
+/// [`InterfaceEvent`]s
+///
+/// Kernel interfaces are followed through the rtnetlink link multicast group,
+/// and userspace implementations through their sockets in `/var/run/wireguard`,
+/// which is created if missing. Both report the same interface, so each
+/// change is only reported once per interface name.
+#[derive(Debug)]
+pub struct InterfaceEventWatcher {
+    messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
+    inotify: Async<InotifyFd>,
+    tracker: InterfaceTracker,
+}
+
+impl InterfaceEventWatcher {
+    /// Subscribe to both sources, and record the current interfaces without
+    /// producing any events
+    pub async fn new() -> Result<Self, WireCtlError> {
+        let timeouts = default_timeouts();
+
+        let (mut connection, handle, messages) = new_connection_with_socket::<SmolSocket>()?;
+        connection
+            .socket_mut()
+            .socket_mut()
+            .bind(&SocketAddr::new(0, RTMGRP_LINK))?;
+        smol::spawn(connection).detach();
+
+        async_fs::create_dir_all(WG_SOCKET_PATH).await?;
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .map_err(io::Error::from)?;
+        let inotify = InotifyFd(inotify);
+        inotify
+            .0
+            .add_watch(
+                WG_SOCKET_PATH,
+                AddWatchFlags::IN_CREATE
+                    | AddWatchFlags::IN_DELETE
+                    | AddWatchFlags::IN_MOVED_FROM
+                    | AddWatchFlags::IN_MOVED_TO,
+            )
+            .map_err(io::Error::from)?;
+
+        // Changes racing with the dump are received again, and deduplicated
+        let mut tracker = InterfaceTracker::default();
+        let links = handle.link().get().execute();
+        let links: Vec<_> = with_timeout(&timeouts, TimeoutPhase::Read, async {
+            Ok(links.try_collect().await?)
+        })
+        .await?;
+        for link in links {
+            if let Some(change) = link_change(link) {
+                tracker.apply(change);
+            }
+        }
+        for ifname in ipc::list_interfaces(&timeouts).await? {
+            tracker.apply(Change::Socket {
+                ifname,
+                present: true,
+            });
+        }
+
+        Ok(Self {
+            messages,
+            inotify: Async::new(inotify)?,
+            tracker,
+        })
+    }
+
+    /// Wait for the next change of either source, and return its events
+    ///
+    /// Changes of links which aren't Wireguard interfaces produce no events.
+    /// Fails with an [`io::ErrorKind::UnexpectedEof`] error once the rtnetlink
+    /// connection is closed.
+    pub async fn next_events(&mut self) -> Result<Vec<InterfaceEvent>, WireCtlError> {
+        let messages = &mut self.messages;
+        let inotify = &self.inotify;
+        let (changes, overflow): (Vec<Change>, bool) = smol::future::or(
+            async {
+                let (message, _) = messages
+                    .next()
+                    .await
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                Ok::<_, WireCtlError>((message_change(message).into_iter().collect(), false))
+            },
+            async {
+                let events = inotify
+                    .read_with(|fd| fd.0.read_events().map_err(io::Error::from))
+                    .await?;
+                let overflow = events
+                    .iter()
+                    .any(|event| event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW));
+                Ok((
+                    events.into_iter().filter_map(socket_change).collect(),
+                    overflow,
+                ))
+            },
+        )
+        .await?;
+
+        let mut events: Vec<_> = changes
+            .into_iter()
+            .flat_map(|change| self.tracker.apply(change))
+            .collect();
+        if overflow {
+            // Changes of the socket directory were dropped by the kernel
+            let ifnames = ipc::list_interfaces(&default_timeouts()).await?;
+            events.extend(self.tracker.sync_sockets(ifnames));
+        }
+        Ok(events)
+    }
+
+    /// Turn the watcher into a stream of events, which ends when the rtnetlink
+    /// connection is closed
+    pub fn into_stream(self) -> impl Stream<Item = Result<InterfaceEvent, WireCtlError>> {
+        futures::stream::unfold(
+            (self, VecDeque::new()),
+            |(mut watcher, mut pending)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((Ok(event), (watcher, pending)));
+                    }
+                    match watcher.next_events().await {
+                        Ok(events) => pending.extend(events),
+                        Err(WireCtlError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                            return None
+                        }
+                        Err(e) => return Some((Err(e), (watcher, pending))),
+                    }
+                }
+            },
+        )
+    }
+}
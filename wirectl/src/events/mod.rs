@@ -0,0 +1,221 @@
+//! Peer activity and interface lifecycle events
+//!
+//! [`PeerEventWatcher`] polls an interface periodically, and derives typed
+//! events by comparing successive configurations. [`InterfaceEventWatcher`]
+//! is notified by the kernel of the interfaces being added and removed.
+use crate::{interface::WgInterface, types::*, WireCtlError};
+use futures::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use smol::Timer;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+mod interface;
+
+pub use interface::*;
+
+pub const DEFAULT_EVENTS_INTERVAL: Duration = Duration::from_secs(1);
+/// Wireguard stops using a session after this time since its handshake
+pub const REJECT_AFTER_TIME: Duration = Duration::from_secs(180);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerEvent {
+    pub public_key: PublicKey,
+    pub kind: PeerEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PeerEventKind {
+    PeerAdded,
+    PeerRemoved,
+    /// A new handshake has been completed
+    HandshakeCompleted {
+        time: SystemTime,
+    },
+    /// No handshake has been completed within [`REJECT_AFTER_TIME`]
+    HandshakeExpired {
+        last_handshake: SystemTime,
+    },
+    EndpointChanged {
+        old: Option<SocketAddr>,
+        new: Option<SocketAddr>,
+    },
+    /// The peer started transferring data
+    TrafficStarted,
+    /// The peer didn't transfer any data since the previous poll
+    TrafficStopped,
+}
+
+#[derive(Clone, Debug)]
+struct PeerState {
+    last_handshake: Option<SystemTime>,
+    endpoint: Option<SocketAddr>,
+    rx_bytes: u64,
+    tx_bytes: u64,
+    active: bool,
+    expired: bool,
+}
+
+impl PeerState {
+    fn new(peer: &Peer, now: SystemTime) -> Self {
+        Self {
+            last_handshake: peer.last_handshake,
+            endpoint: peer.endpoint,
+            rx_bytes: peer.rx_bytes,
+            tx_bytes: peer.tx_bytes,
+            active: false,
+            expired: handshake_expired(peer.last_handshake, now),
+        }
+    }
+}
+
+/// Polls an interface and produces [`PeerEvent`]s
+#[derive(Debug)]
+pub struct PeerEventWatcher {
+    wgif: WgInterface,
+    interval: Duration,
+    last_poll: Option<Instant>,
+    peers: Option<HashMap<PublicKey, PeerState>>,
+}
+
+impl PeerEventWatcher {
+    pub fn new(wgif: WgInterface) -> Self {
+        Self {
+            wgif,
+            interval: DEFAULT_EVENTS_INTERVAL,
+            last_poll: None,
+            peers: None,
+        }
+    }
+
+    pub fn set_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn interface(&self) -> &WgInterface {
+        &self.wgif
+    }
+
+    /// Wait for the next poll, and return the events since the previous poll
+    ///
+    /// The first call returns immediately, and only records the current state
+    /// of the peers without producing any events.
+    pub async fn next_events(&mut self) -> Result<Vec<PeerEvent>, WireCtlError> {
+        if let Some(last_poll) = self.last_poll {
+            Timer::at(last_poll + self.interval).await;
+        }
+        self.last_poll = Some(Instant::now());
+
+        let device = self.wgif.get_config().await?;
+        Ok(self.update(&device, SystemTime::now()))
+    }
+
+    /// Turn the watcher into an endless stream of events
+    pub fn into_stream(self) -> impl Stream<Item = Result<PeerEvent, WireCtlError>> {
+        futures::stream::unfold(
+            (self, VecDeque::new()),
+            |(mut watcher, mut pending)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((Ok(event), (watcher, pending)));
+                    }
+                    match watcher.next_events().await {
+                        Ok(events) => pending.extend(events),
+                        Err(e) => return Some((Err(e), (watcher, pending))),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Compare `device` against the previous poll
+    pub(crate) fn update(&mut self, device: &WgDevice, now: SystemTime) -> Vec<PeerEvent> {
+        let mut events = Vec::new();
+        let mut peers = HashMap::with_capacity(device.peers.len());
+
+        let previous = match self.peers.take() {
+            Some(previous) => previous,
+            None => {
+                for peer in &device.peers {
+                    peers.insert(peer.public_key.clone(), PeerState::new(peer, now));
+                }
+                self.peers = Some(peers);
+                return events;
+            }
+        };
+
+        for peer in &device.peers {
+            let mut push = |kind| {
+                events.push(PeerEvent {
+                    public_key: peer.public_key.clone(),
+                    kind,
+                })
+            };
+
+            let state = match previous.get(&peer.public_key) {
+                Some(old) => {
+                    let mut state = PeerState::new(peer, now);
+
+                    if let Some(time) = peer.last_handshake {
+                        if peer.last_handshake > old.last_handshake {
+                            push(PeerEventKind::HandshakeCompleted { time });
+                        }
+                        if state.expired && !old.expired {
+                            push(PeerEventKind::HandshakeExpired {
+                                last_handshake: time,
+                            });
+                        }
+                    }
+                    if state.endpoint != old.endpoint {
+                        push(PeerEventKind::EndpointChanged {
+                            old: old.endpoint,
+                            new: state.endpoint,
+                        });
+                    }
+
+                    state.active = peer.rx_bytes != old.rx_bytes || peer.tx_bytes != old.tx_bytes;
+                    if state.active && !old.active {
+                        push(PeerEventKind::TrafficStarted);
+                    } else if !state.active && old.active {
+                        push(PeerEventKind::TrafficStopped);
+                    }
+                    state
+                }
+                None => {
+                    push(PeerEventKind::PeerAdded);
+                    PeerState::new(peer, now)
+                }
+            };
+            peers.insert(peer.public_key.clone(), state);
+        }
+
+        for public_key in previous.keys() {
+            if !peers.contains_key(public_key) {
+                events.push(PeerEvent {
+                    public_key: public_key.clone(),
+                    kind: PeerEventKind::PeerRemoved,
+                });
+            }
+        }
+
+        self.peers = Some(peers);
+        events
+    }
+}
+
+fn handshake_expired(last_handshake: Option<SystemTime>, now: SystemTime) -> bool {
+    match last_handshake.map(|t| now.duration_since(t)) {
+        Some(Ok(age)) => age >= REJECT_AFTER_TIME,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests;
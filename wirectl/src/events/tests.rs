@@ -0,0 +1,183 @@
+use super::*;
+use crate::api::WgApi;
+
+fn pubkey(n: u8) -> PublicKey {
+    PublicKey::from([n; WG_KEY_LEN])
+}
+
+fn device(peers: Vec<Peer>) -> WgDevice {
+    let mut device = WgDevice::new("test");
+    device.peers = peers;
+    device
+}
+
+#[test]
+fn events_added_removed() {
+    let mut watcher = PeerEventWatcher::new(WgInterface::new("test", WgApi::IPC));
+    let now = SystemTime::now();
+
+    // The first poll is the baseline
+    let events = watcher.update(&device(vec![Peer::new(pubkey(1))]), now);
+    assert!(events.is_empty());
+
+    let events = watcher.update(&device(vec![Peer::new(pubkey(2))]), now);
+    assert_eq!(events.len(), 2);
+    assert!(events.contains(&PeerEvent {
+        public_key: pubkey(2),
+        kind: PeerEventKind::PeerAdded,
+    }));
+    assert!(events.contains(&PeerEvent {
+        public_key: pubkey(1),
+        kind: PeerEventKind::PeerRemoved,
+    }));
+}
+
+#[test]
+fn events_handshake_and_traffic() {
+    let mut watcher = PeerEventWatcher::new(WgInterface::new("test", WgApi::IPC));
+    let start = SystemTime::now();
+    let mut peer = Peer::new(pubkey(1));
+    watcher.update(&device(vec![peer.clone()]), start);
+
+    let endpoint: SocketAddr = "192.0.2.1:51820".parse().unwrap();
+    peer.last_handshake = Some(start);
+    peer.endpoint = Some(endpoint);
+    peer.rx_bytes = 148;
+    let events = watcher.update(&device(vec![peer.clone()]), start);
+    let kinds: Vec<_> = events.into_iter().map(|e| e.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            PeerEventKind::HandshakeCompleted { time: start },
+            PeerEventKind::EndpointChanged {
+                old: None,
+                new: Some(endpoint),
+            },
+            PeerEventKind::TrafficStarted,
+        ]
+    );
+
+    let later = start + REJECT_AFTER_TIME;
+    let events = watcher.update(&device(vec![peer.clone()]), later);
+    let kinds: Vec<_> = events.into_iter().map(|e| e.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            PeerEventKind::HandshakeExpired {
+                last_handshake: start,
+            },
+            PeerEventKind::TrafficStopped,
+        ]
+    );
+
+    // Expiration is only reported once
+    let events = watcher.update(&device(vec![peer]), later + Duration::from_secs(1));
+    assert!(events.is_empty());
+}
+
+fn link(index: u32, ifname: &str, wireguard: bool, up: bool) -> Change {
+    Change::Link {
+        index,
+        ifname: ifname.to_owned(),
+        wireguard,
+        up,
+    }
+}
+
+fn interface_kinds(events: Vec<InterfaceEvent>) -> Vec<InterfaceEventKind> {
+    events
+        .into_iter()
+        .inspect(|e| assert_eq!(e.ifname, "wg0"))
+        .map(|e| e.kind)
+        .collect()
+}
+
+#[test]
+fn interface_events_kernel() {
+    let mut tracker = InterfaceTracker::default();
+
+    // Other links are ignored
+    assert!(tracker.apply(link(1, "eth0", false, true)).is_empty());
+
+    let events = tracker.apply(link(2, "wg0", true, false));
+    assert_eq!(
+        interface_kinds(events),
+        vec![InterfaceEventKind::InterfaceAdded]
+    );
+    let events = tracker.apply(link(2, "wg0", true, true));
+    assert_eq!(interface_kinds(events), vec![InterfaceEventKind::LinkUp]);
+    // Repeated notifications don't produce events
+    assert!(tracker.apply(link(2, "wg0", true, true)).is_empty());
+    let events = tracker.apply(link(2, "wg0", true, false));
+    assert_eq!(interface_kinds(events), vec![InterfaceEventKind::LinkDown]);
+
+    let events = tracker.apply(Change::LinkRemoved { index: 2 });
+    assert_eq!(
+        interface_kinds(events),
+        vec![InterfaceEventKind::InterfaceRemoved]
+    );
+}
+
+#[test]
+fn interface_events_userspace() {
+    let mut tracker = InterfaceTracker::default();
+    let socket = |present| Change::Socket {
+        ifname: "wg0".to_owned(),
+        present,
+    };
+
+    // The tun device alone isn't an interface
+    assert!(tracker.apply(link(3, "wg0", false, false)).is_empty());
+    let events = tracker.apply(socket(true));
+    assert_eq!(
+        interface_kinds(events),
+        vec![InterfaceEventKind::InterfaceAdded]
+    );
+    let events = tracker.apply(link(3, "wg0", false, true));
+    assert_eq!(interface_kinds(events), vec![InterfaceEventKind::LinkUp]);
+
+    // Reported once, whichever source notices the removal first
+    let events = tracker.apply(socket(false));
+    assert_eq!(
+        interface_kinds(events),
+        vec![InterfaceEventKind::InterfaceRemoved]
+    );
+    assert!(tracker.apply(Change::LinkRemoved { index: 3 }).is_empty());
+
+    // A link renamed away leaves the interface without its link
+    tracker.apply(socket(true));
+    tracker.apply(link(3, "wg0", false, true));
+    let events = tracker.apply(link(3, "wg1", false, true));
+    assert_eq!(interface_kinds(events), vec![InterfaceEventKind::LinkDown]);
+}
+
+#[test]
+fn interface_events_resync() {
+    let mut tracker = InterfaceTracker::default();
+    let socket = |ifname: &str| Change::Socket {
+        ifname: ifname.to_owned(),
+        present: true,
+    };
+    tracker.apply(socket("wg0"));
+    tracker.apply(socket("wg1"));
+
+    // Sockets missed while the inotify queue overflowed
+    let mut events = tracker.sync_sockets(vec!["wg1".to_owned(), "wg2".to_owned()]);
+    events.sort_by(|a, b| a.ifname.cmp(&b.ifname));
+    assert_eq!(
+        events,
+        vec![
+            InterfaceEvent {
+                ifname: "wg0".to_owned(),
+                kind: InterfaceEventKind::InterfaceRemoved,
+            },
+            InterfaceEvent {
+                ifname: "wg2".to_owned(),
+                kind: InterfaceEventKind::InterfaceAdded,
+            },
+        ]
+    );
+    assert!(tracker
+        .sync_sockets(vec!["wg1".to_owned(), "wg2".to_owned()])
+        .is_empty());
+}
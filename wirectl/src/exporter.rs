@@ -0,0 +1,290 @@
+use crate::args::ExporterCmd;
+use futures::io::BufReader;
+use futures::prelude::*;
+use smol::net::{TcpListener, TcpStream};
+use smol::Timer;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use wirectl::interface::WgInterface;
+use wirectl::timeout::Timeouts;
+use wirectl::types::{Peer, PublicKey, WgDevice};
+use wirectl::WireCtlError;
+
+type PeerNames = HashMap<PublicKey, String>;
+
+/// Deadline of reading a request, and of writing its response
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Deadline of each phase of the queries to the interfaces, so that a stuck
+/// implementation doesn't hang the scrapes
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run `fut`, failing with [`io::ErrorKind::TimedOut`] after `timeout`
+async fn within<T, E, F>(timeout: Duration, fut: F) -> Result<T, E>
+where
+    E: From<io::Error>,
+    F: Future<Output = Result<T, E>>,
+{
+    smol::future::or(fut, async {
+        Timer::after(timeout).await;
+        Err(io::Error::from(io::ErrorKind::TimedOut).into())
+    })
+    .await
+}
+
+pub async fn cmd_exporter(opt: &ExporterCmd) -> Result<(), WireCtlError> {
+    let names = match &opt.peer_names {
+        Some(path) => load_peer_names(path).await?,
+        None => PeerNames::new(),
+    };
+    let names = Arc::new(names);
+
+    let listener = TcpListener::bind(opt.listen).await?;
+    eprintln!("Serving metrics on http://{}/metrics", opt.listen);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let names = names.clone();
+        smol::spawn(async move {
+            if let Err(e) = handle_connection(stream, &names).await {
+                eprintln!("Failed to serve metrics: {}", e);
+            }
+        })
+        .detach();
+    }
+}
+
+/// Parse the peer names file
+///
+/// Each line contains a base64 public key and its name, separated by whitespace.
+/// Empty lines and lines starting with `#` are ignored.
+async fn load_peer_names(path: &Path) -> Result<PeerNames, WireCtlError> {
+    let content = smol::fs::read_to_string(path).await?;
+    let mut names = PeerNames::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, name) = line
+            .split_once(char::is_whitespace)
+            .ok_or(WireCtlError::InvalidConfig)?;
+        names.insert(PublicKey::from_base64(key)?, name.trim().to_owned());
+    }
+
+    Ok(names)
+}
+
+async fn handle_connection(stream: TcpStream, names: &PeerNames) -> Result<(), WireCtlError> {
+    let mut reader = BufReader::new(stream.clone());
+    let mut writer = stream;
+
+    let request_line = within(HTTP_TIMEOUT, async {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        // Skip the headers
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+        Ok::<_, io::Error>(request_line)
+    })
+    .await?;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match render_metrics(names).await {
+            Ok(body) => ("200 OK", body),
+            Err(e) => ("500 Internal Server Error", format!("{}\n", e)),
+        },
+        (Some("GET"), Some(_)) => ("404 Not Found", "Not Found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_owned()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    within(HTTP_TIMEOUT, async {
+        writer.write_all(response.as_bytes()).await?;
+        writer.flush().await
+    })
+    .await?;
+    Ok(())
+}
+
+async fn render_metrics(names: &PeerNames) -> Result<String, WireCtlError> {
+    let mut devices = Vec::new();
+    for wgif in within(QUERY_TIMEOUT, WgInterface::get_interfaces()).await? {
+        let wgif = wgif.with_timeouts(Timeouts::all(QUERY_TIMEOUT));
+        // An interface may be removed while scraping, which shouldn't hide the others
+        match wgif.get_config().await {
+            Ok(device) => devices.push(device),
+            Err(e) => eprintln!(
+                "Failed to get the configuration of {}: {}",
+                wgif.ifname(),
+                e
+            ),
+        }
+    }
+    Ok(render_devices(&devices, names))
+}
+
+fn render_devices(devices: &[WgDevice], names: &PeerNames) -> String {
+    let mut out = String::new();
+    write_family(
+        &mut out,
+        "wireguard_interface_info",
+        "gauge",
+        "Information of the interface",
+        devices.iter().map(|dev| {
+            let public_key = dev
+                .public_key
+                .as_ref()
+                .map(|k| k.to_base64())
+                .unwrap_or_default();
+            (
+                labels(&[("interface", &dev.device_name), ("public_key", &public_key)]),
+                1,
+            )
+        }),
+    );
+    write_family(
+        &mut out,
+        "wireguard_listen_port",
+        "gauge",
+        "UDP port the interface listens on",
+        devices.iter().map(|dev| {
+            (
+                labels(&[("interface", &dev.device_name)]),
+                dev.listen_port as u64,
+            )
+        }),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peers",
+        "gauge",
+        "Number of peers of the interface",
+        devices.iter().map(|dev| {
+            (
+                labels(&[("interface", &dev.device_name)]),
+                dev.peers.len() as u64,
+            )
+        }),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peer_info",
+        "gauge",
+        "Information of the peer",
+        peers(devices).map(|(dev, peer)| {
+            let endpoint = peer.endpoint.map(|e| e.to_string()).unwrap_or_default();
+            (peer_labels(dev, peer, names, &[("endpoint", &endpoint)]), 1)
+        }),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peer_receive_bytes_total",
+        "counter",
+        "Bytes received from the peer",
+        peers(devices).map(|(dev, peer)| (peer_labels(dev, peer, names, &[]), peer.rx_bytes)),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peer_transmit_bytes_total",
+        "counter",
+        "Bytes sent to the peer",
+        peers(devices).map(|(dev, peer)| (peer_labels(dev, peer, names, &[]), peer.tx_bytes)),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peer_latest_handshake_seconds",
+        "gauge",
+        "UNIX timestamp of the latest handshake with the peer, 0 if never",
+        peers(devices).map(|(dev, peer)| {
+            let secs = peer
+                .last_handshake
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            (peer_labels(dev, peer, names, &[]), secs)
+        }),
+    );
+    write_family(
+        &mut out,
+        "wireguard_peer_allowed_ips",
+        "gauge",
+        "Number of allowed IPs of the peer",
+        peers(devices).map(|(dev, peer)| {
+            (
+                peer_labels(dev, peer, names, &[]),
+                peer.allow_ips.len() as u64,
+            )
+        }),
+    );
+
+    out
+}
+
+fn peers(devices: &[WgDevice]) -> impl Iterator<Item = (&WgDevice, &Peer)> {
+    devices
+        .iter()
+        .flat_map(|dev| dev.peers.iter().map(move |peer| (dev, peer)))
+}
+
+fn peer_labels(dev: &WgDevice, peer: &Peer, names: &PeerNames, extra: &[(&str, &str)]) -> String {
+    let public_key = peer.public_key.to_base64();
+    let name = names.get(&peer.public_key).map_or("", |n| n.as_str());
+    let mut pairs = vec![
+        ("interface", dev.device_name.as_str()),
+        ("public_key", public_key.as_str()),
+        ("name", name),
+    ];
+    pairs.extend_from_slice(extra);
+    labels(&pairs)
+}
+
+fn write_family<I>(out: &mut String, name: &str, kind: &str, help: &str, samples: I)
+where
+    I: Iterator<Item = (String, u64)>,
+{
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let mut s = String::from("{");
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+        }
+        s.push_str(key);
+        s.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => s.push_str("\\\\"),
+                '"' => s.push_str("\\\""),
+                '\n' => s.push_str("\\n"),
+                c => s.push(c),
+            }
+        }
+        s.push('"');
+    }
+    s.push('}');
+    s
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,90 @@
+use super::*;
+use std::time::Duration;
+use wirectl::types::WG_KEY_LEN;
+
+fn device() -> WgDevice {
+    let mut device = WgDevice::new("wg0");
+    device.public_key = Some(PublicKey::from([1; WG_KEY_LEN]));
+    device.listen_port = 51820;
+
+    let mut named = Peer::new(PublicKey::from([2; WG_KEY_LEN]));
+    named.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+    named.last_handshake = Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    named.rx_bytes = 1000;
+    named.tx_bytes = 2000;
+    named.allow_ips = vec!["10.0.0.2/32".parse().unwrap()];
+    device.peers.push(named);
+    device
+        .peers
+        .push(Peer::new(PublicKey::from([3; WG_KEY_LEN])));
+    device
+}
+
+#[test]
+fn exporter_render() {
+    let mut names = PeerNames::new();
+    names.insert(PublicKey::from([2; WG_KEY_LEN]), "laptop".to_owned());
+    let out = render_devices(&[device()], &names);
+    let lines: Vec<_> = out.lines().collect();
+
+    let key1 = PublicKey::from([1; WG_KEY_LEN]).to_base64();
+    let key2 = PublicKey::from([2; WG_KEY_LEN]).to_base64();
+    let key3 = PublicKey::from([3; WG_KEY_LEN]).to_base64();
+    for expected in [
+        "# HELP wireguard_interface_info Information of the interface".to_owned(),
+        "# TYPE wireguard_interface_info gauge".to_owned(),
+        format!(
+            r#"wireguard_interface_info{{interface="wg0",public_key="{}"}} 1"#,
+            key1
+        ),
+        r#"wireguard_listen_port{interface="wg0"} 51820"#.to_owned(),
+        r#"wireguard_peers{interface="wg0"} 2"#.to_owned(),
+        format!(
+            r#"wireguard_peer_info{{interface="wg0",public_key="{}",name="laptop",endpoint="192.0.2.1:51820"}} 1"#,
+            key2
+        ),
+        format!(
+            r#"wireguard_peer_info{{interface="wg0",public_key="{}",name="",endpoint=""}} 1"#,
+            key3
+        ),
+        "# TYPE wireguard_peer_receive_bytes_total counter".to_owned(),
+        format!(
+            r#"wireguard_peer_receive_bytes_total{{interface="wg0",public_key="{}",name="laptop"}} 1000"#,
+            key2
+        ),
+        format!(
+            r#"wireguard_peer_transmit_bytes_total{{interface="wg0",public_key="{}",name="laptop"}} 2000"#,
+            key2
+        ),
+        format!(
+            r#"wireguard_peer_latest_handshake_seconds{{interface="wg0",public_key="{}",name="laptop"}} 1600000000"#,
+            key2
+        ),
+        format!(
+            r#"wireguard_peer_latest_handshake_seconds{{interface="wg0",public_key="{}",name=""}} 0"#,
+            key3
+        ),
+        format!(
+            r#"wireguard_peer_allowed_ips{{interface="wg0",public_key="{}",name="laptop"}} 1"#,
+            key2
+        ),
+    ] {
+        assert!(lines.contains(&expected.as_str()), "missing {}", expected);
+    }
+}
+
+#[test]
+fn exporter_render_empty() {
+    let out = render_devices(&[], &PeerNames::new());
+    // Every family is still described
+    assert_eq!(out.lines().count(), 16);
+    assert!(out.lines().all(|line| line.starts_with('#')));
+}
+
+#[test]
+fn exporter_labels_escape() {
+    assert_eq!(
+        labels(&[("name", "a \"quoted\"\\name\n"), ("interface", "wg0")]),
+        r#"{name="a \"quoted\"\\name\n",interface="wg0"}"#
+    );
+}
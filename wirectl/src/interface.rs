@@ -1,19 +1,43 @@
 //! Types related to Wireguard devices
 use crate::types::*;
-use futures::{StreamExt, TryFutureExt, TryStreamExt};
 
 use crate::{
+    acl::Acl,
     api::{WgApi, AVAILABLE_WG_APIS},
+    netlink::{self, LinkInfo, LinkStats, RouteChanges, RouteOptions},
+    netns::NetNs,
+    nftables,
+    portforward::PortForwarding,
+    shaping::Shaping,
+    timeout::{default_timeouts, Timeouts},
     WireCtlError,
 };
+use ipnetwork::IpNetwork;
 
 #[derive(Clone, Debug)]
 pub struct WgInterface {
     ifname: String,
     wgapi: WgApi,
+    timeouts: Option<Timeouts>,
+    acl: Option<Acl>,
+    port_forwarding: Option<PortForwarding>,
+    shaping: Option<Shaping>,
+    netns: Option<NetNs>,
 }
 
 impl WgInterface {
+    pub(crate) fn new(ifname: &str, wgapi: WgApi) -> Self {
+        WgInterface {
+            ifname: ifname.to_owned(),
+            wgapi,
+            timeouts: None,
+            acl: None,
+            port_forwarding: None,
+            shaping: None,
+            netns: None,
+        }
+    }
+
     pub async fn create_interface(ifname: &str) -> Result<WgInterface, WireCtlError> {
         Self::create_interface_with(AVAILABLE_WG_APIS[0], ifname).await
     }
@@ -22,23 +46,42 @@ impl WgInterface {
         api: WgApi,
         ifname: &str,
     ) -> Result<WgInterface, WireCtlError> {
-        api.add_interface(ifname).await?;
+        api.add_interface(ifname, None, &default_timeouts()).await?;
 
-        Ok(WgInterface {
-            ifname: ifname.to_owned(),
-            wgapi: api,
-        })
+        Ok(WgInterface::new(ifname, api))
+    }
+
+    /// Create the interface inside `netns`, which the returned interface
+    /// operates in
+    ///
+    /// Userspace implementations are launched inside `netns`, so that their
+    /// UDP socket is bound there as well. To keep the socket in the current
+    /// namespace, create the interface here and use
+    /// [`WgInterface::move_to_netns()`] instead.
+    pub async fn create_interface_in(
+        ifname: &str,
+        netns: NetNs,
+    ) -> Result<WgInterface, WireCtlError> {
+        let api = AVAILABLE_WG_APIS[0];
+        api.add_interface(ifname, Some(&netns), &default_timeouts())
+            .await?;
+
+        Ok(WgInterface::new(ifname, api).with_netns(netns))
     }
 
     pub async fn get_interface(ifname: &str) -> Result<WgInterface, WireCtlError> {
+        Self::find_interface(ifname, None).await
+    }
+
+    /// The interface `ifname`, reached from inside `netns` if given
+    async fn find_interface(
+        ifname: &str,
+        netns: Option<&NetNs>,
+    ) -> Result<WgInterface, WireCtlError> {
+        let timeouts = default_timeouts();
         for api in AVAILABLE_WG_APIS {
-            match api.check_interface(ifname).await {
-                Ok(_) => {
-                    return Ok(WgInterface {
-                        ifname: ifname.to_owned(),
-                        wgapi: api,
-                    })
-                }
+            match api.check_interface(ifname, netns, &timeouts).await {
+                Ok(_) => return Ok(WgInterface::new(ifname, api)),
                 Err(e) => {
                     if let WireCtlError::NotFound = e {
                         continue;
@@ -53,38 +96,438 @@ impl WgInterface {
     }
 
     pub async fn get_interfaces() -> Result<Vec<WgInterface>, WireCtlError> {
-        futures::stream::iter(AVAILABLE_WG_APIS.iter().copied())
-            .then(|api| {
-                api.list_interfaces().map_ok(move |l| {
-                    l.into_iter()
-                        .map(|ifname| WgInterface { ifname, wgapi: api })
-                        .collect()
-                })
-            })
-            .try_concat()
-            .await
+        let timeouts = default_timeouts();
+        let mut interfaces = Vec::new();
+        for api in AVAILABLE_WG_APIS {
+            let list = api.list_interfaces(&timeouts).await?;
+            interfaces.extend(list.iter().map(|ifname| WgInterface::new(ifname, api)));
+        }
+        Ok(interfaces)
+    }
+
+    /// The interface `ifname`, whose link is inside `netns`
+    ///
+    /// Fails with [`WireCtlError::NotFound`] if the link isn't there.
+    pub async fn get_interface_in(ifname: &str, netns: NetNs) -> Result<WgInterface, WireCtlError> {
+        let wgif = Self::find_interface(ifname, Some(&netns)).await?;
+        let handle = netlink::connect(Some(&netns))?;
+        netlink::link_index(&handle, ifname, &wgif.timeouts()).await?;
+        Ok(wgif.with_netns(netns))
+    }
+
+    /// The interfaces whose link is inside `netns`
+    pub async fn get_interfaces_in(netns: NetNs) -> Result<Vec<WgInterface>, WireCtlError> {
+        let timeouts = default_timeouts();
+        let handle = netlink::connect(Some(&netns))?;
+        let mut interfaces = Vec::new();
+        for wgif in Self::get_interfaces().await? {
+            match netlink::link_index(&handle, &wgif.ifname, &timeouts).await {
+                Ok(_) => interfaces.push(wgif.with_netns(netns.clone())),
+                Err(WireCtlError::NotFound) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(interfaces)
     }
 
     pub async fn list_interfaces() -> Result<Vec<String>, WireCtlError> {
-        futures::stream::iter(&AVAILABLE_WG_APIS)
-            .then(|api| api.list_interfaces())
-            .try_concat()
-            .await
+        let timeouts = default_timeouts();
+        let mut interfaces = Vec::new();
+        for api in AVAILABLE_WG_APIS {
+            interfaces.extend(api.list_interfaces(&timeouts).await?);
+        }
+        Ok(interfaces)
+    }
+
+    /// Override the process-wide default deadlines for operations on this interface
+    ///
+    /// As `WgInterface` is cheap to clone, a deadline for a single call can be
+    /// set by `wgif.clone().with_timeouts(..)`.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
+    /// The deadlines used by operations on this interface
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts.unwrap_or_else(default_timeouts)
+    }
+
+    /// Operate on the link inside `netns`, where it already is
+    ///
+    /// Every operation then opens its sockets from inside `netns`, including
+    /// the connections to the socket of a userspace implementation.
+    pub fn with_netns(mut self, netns: NetNs) -> Self {
+        self.netns = Some(netns);
+        self
+    }
+
+    /// The namespace of the link, or `None` for the current one
+    pub fn netns(&self) -> Option<&NetNs> {
+        self.netns.as_ref()
+    }
+
+    /// Move the link to `netns`, and operate on it there from now on
+    ///
+    /// The UDP socket of the interface stays in the namespace which created
+    /// it, so the tunnel keeps its endpoint there. The link comes down without
+    /// its addresses and routes, which have to be set again.
+    pub async fn move_to_netns(self, netns: NetNs) -> Result<WgInterface, WireCtlError> {
+        netlink::move_to_netns(&self.ifname, &netns, self.netns(), &self.timeouts()).await?;
+        Ok(self.with_netns(netns))
+    }
+
+    /// Filter the traffic of the peers with `acl`
+    ///
+    /// The ACL is applied by [`WgInterface::apply_acl()`], then again after
+    /// every change of the configuration, so that it follows the peers being
+    /// added and removed.
+    pub fn with_acl(mut self, acl: Acl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    pub fn acl(&self) -> Option<&Acl> {
+        self.acl.as_ref()
+    }
+
+    /// Forward ports to the peers with `forwarding`
+    ///
+    /// Like the ACL, the rules are installed by
+    /// [`WgInterface::apply_port_forwarding()`], then updated after every
+    /// change of the configuration, following the allowed IPs of the peers.
+    pub fn with_port_forwarding(mut self, forwarding: PortForwarding) -> Self {
+        self.port_forwarding = Some(forwarding);
+        self
+    }
+
+    pub fn port_forwarding(&self) -> Option<&PortForwarding> {
+        self.port_forwarding.as_ref()
+    }
+
+    /// Limit the bandwidth of the peers with `shaping`
+    ///
+    /// The qdiscs are set up by [`WgInterface::apply_shaping()`], then again
+    /// after every change of the configuration. The kernel removes them with
+    /// the link.
+    pub fn with_shaping(mut self, shaping: Shaping) -> Self {
+        self.shaping = Some(shaping);
+        self
+    }
+
+    pub fn shaping(&self) -> Option<&Shaping> {
+        self.shaping.as_ref()
     }
 
     pub async fn get_config(&self) -> Result<WgDevice, WireCtlError> {
-        self.wgapi.get_config(&self.ifname).await
+        let mut device = self.device().await?;
+        // The userspace implementations don't know the index of the link, and
+        // may answer without a link of that name
+        if device.ifindex == 0 {
+            let handle = netlink::connect(self.netns())?;
+            match netlink::link_index(&handle, &self.ifname, &self.timeouts()).await {
+                Ok(index) => device.ifindex = index,
+                Err(WireCtlError::NotFound) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(device)
     }
 
+    /// The configuration of the device, leaving its `ifindex` as reported
+    async fn device(&self) -> Result<WgDevice, WireCtlError> {
+        self.wgapi
+            .get_config(&self.ifname, self.netns(), &self.timeouts())
+            .await
+    }
+
+    /// Apply `conf` to the device
+    ///
+    /// The ACL, port forwarding and shaping set on the interface are then
+    /// updated for the new peers, all from one read of the configuration. This
+    /// happens once Wireguard has taken the change: if one of them fails, the
+    /// error is returned with the change kept, and that one and the ones after
+    /// it stay as they were until applied again.
     pub async fn set_config(&self, conf: WgDeviceSetter) -> Result<(), WireCtlError> {
         if conf.devname != self.ifname {
             return Err(WireCtlError::InvalidConfig);
         }
-        self.wgapi.set_config(&self.ifname, conf).await
+        // The peers are checked against the key of the device if the changes
+        // don't set one
+        if conf.privkey.is_some() {
+            conf.validate()?;
+        } else {
+            conf.validate_for(&self.device().await?)?;
+        }
+        self.wgapi
+            .set_config(&self.ifname, conf, self.netns(), &self.timeouts())
+            .await?;
+
+        if self.acl.is_none() && self.port_forwarding.is_none() && self.shaping.is_none() {
+            return Ok(());
+        }
+        let device = self.device().await?;
+        if let Some(acl) = &self.acl {
+            nftables::apply_acl(&self.ifname, acl, &device, self.netns(), &self.timeouts()).await?;
+        }
+        if let Some(forwarding) = &self.port_forwarding {
+            nftables::apply_port_forwarding(
+                &self.ifname,
+                forwarding,
+                &device,
+                self.netns(),
+                &self.timeouts(),
+            )
+            .await?;
+        }
+        if let Some(shaping) = &self.shaping {
+            netlink::apply_shaping(
+                &self.ifname,
+                shaping,
+                &device,
+                self.netns(),
+                &self.timeouts(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_peer(&self, public_key: &PublicKey) -> Result<Peer, WireCtlError> {
+        let device = self.device().await?;
+        device
+            .peers
+            .into_iter()
+            .find(|peer| &peer.public_key == public_key)
+            .ok_or_else(|| WireCtlError::PeerNotFound(public_key.clone()))
+    }
+
+    /// Add a new peer
+    ///
+    /// Fails with [`WireCtlError::PeerAlreadyExists`] if the peer is already on the interface.
+    /// The check is best-effort: a peer added concurrently after it is updated
+    /// with `peer` instead.
+    pub async fn add_peer(&self, peer: PeerSetter) -> Result<(), WireCtlError> {
+        if self.has_peer(&peer.pubkey).await? {
+            return Err(WireCtlError::PeerAlreadyExists(peer.pubkey));
+        }
+        self.set_config(WgDeviceSetter::new(&self.ifname).set_peer(peer))
+            .await
+    }
+
+    /// Change an existing peer
+    ///
+    /// Fails with [`WireCtlError::PeerNotFound`] if the peer isn't on the interface.
+    /// The check is best-effort: a peer removed concurrently after it isn't
+    /// added back, as the change only updates existing peers.
+    pub async fn update_peer(&self, peer: PeerSetter) -> Result<(), WireCtlError> {
+        if !self.has_peer(&peer.pubkey).await? {
+            return Err(WireCtlError::PeerNotFound(peer.pubkey));
+        }
+        self.set_config(WgDeviceSetter::new(&self.ifname).set_peer(peer.set_update_only()))
+            .await
+    }
+
+    /// Remove an existing peer
+    ///
+    /// Fails with [`WireCtlError::PeerNotFound`] if the peer isn't on the interface.
+    /// The check is best-effort: removing a peer which was removed concurrently
+    /// after it succeeds without changes.
+    pub async fn remove_peer(&self, public_key: &PublicKey) -> Result<(), WireCtlError> {
+        if !self.has_peer(public_key).await? {
+            return Err(WireCtlError::PeerNotFound(public_key.clone()));
+        }
+        let peer = PeerSetter::new(public_key.clone()).set_remove();
+        self.set_config(WgDeviceSetter::new(&self.ifname).set_peer(peer))
+            .await
+    }
+
+    /// Replace all the peers of the interface with `peers`
+    pub async fn replace_peers(&self, peers: Vec<PeerSetter>) -> Result<(), WireCtlError> {
+        let conf = peers.into_iter().fold(
+            WgDeviceSetter::new(&self.ifname).set_replace_peers(),
+            |conf, peer| conf.set_peer(peer),
+        );
+        self.set_config(conf).await
+    }
+
+    async fn has_peer(&self, public_key: &PublicKey) -> Result<bool, WireCtlError> {
+        let device = self.device().await?;
+        Ok(device
+            .peers
+            .iter()
+            .any(|peer| &peer.public_key == public_key))
+    }
+
+    /// The IPv4 and IPv6 addresses of the link
+    pub async fn addresses(&self) -> Result<Vec<IpNetwork>, WireCtlError> {
+        netlink::get_addresses(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Add an address to the link, such as `10.8.0.1/24`
+    ///
+    /// Fails with [`WireCtlError::AddressAlreadyExists`] if the link already has it.
+    pub async fn add_address(&self, address: IpNetwork) -> Result<(), WireCtlError> {
+        netlink::add_address(&self.ifname, address, self.netns(), &self.timeouts()).await
+    }
+
+    /// Remove an address from the link
+    ///
+    /// Fails with [`WireCtlError::AddressNotFound`] if the link doesn't have it.
+    pub async fn remove_address(&self, address: &IpNetwork) -> Result<(), WireCtlError> {
+        netlink::remove_address(&self.ifname, address, self.netns(), &self.timeouts()).await
+    }
+
+    /// Replace all the addresses of the link with `addresses`
+    ///
+    /// Addresses which the link already has are kept as is.
+    pub async fn set_addresses(&self, addresses: &[IpNetwork]) -> Result<(), WireCtlError> {
+        netlink::set_addresses(&self.ifname, addresses, self.netns(), &self.timeouts()).await
+    }
+
+    /// Bring the link up
+    pub async fn set_link_up(&self) -> Result<(), WireCtlError> {
+        netlink::set_link_up(&self.ifname, true, self.netns(), &self.timeouts()).await
+    }
+
+    /// Bring the link down, which keeps its configuration
+    pub async fn set_link_down(&self) -> Result<(), WireCtlError> {
+        netlink::set_link_up(&self.ifname, false, self.netns(), &self.timeouts()).await
+    }
+
+    pub async fn set_mtu(&self, mtu: u32) -> Result<(), WireCtlError> {
+        netlink::set_mtu(&self.ifname, mtu, self.netns(), &self.timeouts()).await
+    }
+
+    /// Index, flags, MTU and operational state of the link
+    pub async fn link_info(&self) -> Result<LinkInfo, WireCtlError> {
+        netlink::get_link_info(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Kernel counters of the link, including errors and drops
+    pub async fn link_stats(&self) -> Result<LinkStats, WireCtlError> {
+        netlink::get_link_stats(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Route the allowed IPs of the peers through the link
+    ///
+    /// Adds the missing routes and removes the routes it added before which no
+    /// peer allows anymore, leaving the other routes of the link alone. They are
+    /// told apart by their [`RTPROT_WIRECTL`](netlink::RTPROT_WIRECTL) protocol.
+    /// Prefixes already covered by an address of the link are not routed, nor
+    /// `0.0.0.0/0` and `::/0`, which need [`crate::tunnel::FullTunnel`].
+    /// Allowed IPs that another tool or link already routes in the table are
+    /// skipped and listed in [`RouteChanges::skipped`].
+    pub async fn sync_routes(&self, options: &RouteOptions) -> Result<RouteChanges, WireCtlError> {
+        let device = self.device().await?;
+        netlink::sync_routes(
+            &self.ifname,
+            &device,
+            options,
+            self.netns(),
+            &self.timeouts(),
+        )
+        .await
+    }
+
+    /// Forward the traffic of the interface to `egress`, masquerading it
+    ///
+    /// The rules live in the `inet wirectl` nftables table, and replace the
+    /// previous ones of the interface. IP forwarding is enabled in the kernel.
+    ///
+    /// Each table hooked on forwarding gets its own say: a packet accepted here
+    /// is still dropped by a drop rule or policy of another table, such as the
+    /// `filter` table of iptables or of firewalld. The traffic then has to be
+    /// allowed there as well.
+    pub async fn enable_nat(&self, egress: &str) -> Result<(), WireCtlError> {
+        nftables::enable_nat(&self.ifname, egress, self.netns(), &self.timeouts()).await
+    }
+
+    /// Remove the rules added by [`WgInterface::enable_nat()`], if any
+    pub async fn disable_nat(&self) -> Result<(), WireCtlError> {
+        nftables::disable_nat(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Compile the ACL set by [`WgInterface::with_acl()`] against the current
+    /// peers, and replace the nftables rules of the interface with it
+    ///
+    /// Fails with [`WireCtlError::InvalidConfig`] if no ACL is set or a rule
+    /// is invalid.
+    pub async fn apply_acl(&self) -> Result<(), WireCtlError> {
+        let acl = self.acl.as_ref().ok_or(WireCtlError::InvalidConfig)?;
+        let device = self.device().await?;
+        nftables::apply_acl(&self.ifname, acl, &device, self.netns(), &self.timeouts()).await
+    }
+
+    /// Remove the nftables rules of the ACL, if any
+    ///
+    /// The ACL stays set, and is applied again on the next change of the
+    /// configuration.
+    pub async fn remove_acl(&self) -> Result<(), WireCtlError> {
+        nftables::remove_acl(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Install the DNAT and forwarding rules of the ports set by
+    /// [`WgInterface::with_port_forwarding()`], resolving the tunnel addresses
+    /// of the current peers
+    ///
+    /// Fails with [`WireCtlError::InvalidConfig`] if no port forwarding is set
+    /// or it is invalid.
+    pub async fn apply_port_forwarding(&self) -> Result<(), WireCtlError> {
+        let forwarding = self
+            .port_forwarding
+            .as_ref()
+            .ok_or(WireCtlError::InvalidConfig)?;
+        let device = self.device().await?;
+        nftables::apply_port_forwarding(
+            &self.ifname,
+            forwarding,
+            &device,
+            self.netns(),
+            &self.timeouts(),
+        )
+        .await
+    }
+
+    /// Remove the port forwarding rules, if any
+    pub async fn remove_port_forwarding(&self) -> Result<(), WireCtlError> {
+        nftables::remove_port_forwarding(&self.ifname, self.netns(), &self.timeouts()).await
+    }
+
+    /// Update the qdiscs of the link with the limits set by
+    /// [`WgInterface::with_shaping()`], classifying the traffic of the current
+    /// peers by their allowed IPs
+    ///
+    /// Fails with [`WireCtlError::InvalidConfig`] if no shaping is set or it is
+    /// invalid.
+    pub async fn apply_shaping(&self) -> Result<(), WireCtlError> {
+        let shaping = self.shaping.as_ref().ok_or(WireCtlError::InvalidConfig)?;
+        let device = self.device().await?;
+        netlink::apply_shaping(
+            &self.ifname,
+            shaping,
+            &device,
+            self.netns(),
+            &self.timeouts(),
+        )
+        .await
+    }
+
+    /// The limits of the peers, as read back from the kernel
+    pub async fn get_shaping(&self) -> Result<Shaping, WireCtlError> {
+        let device = self.device().await?;
+        netlink::get_shaping(&self.ifname, &device, self.netns(), &self.timeouts()).await
+    }
+
+    /// Restore the default qdisc of the link
+    pub async fn remove_shaping(&self) -> Result<(), WireCtlError> {
+        netlink::remove_shaping(&self.ifname, self.netns(), &self.timeouts()).await
     }
 
     pub async fn remove_interfaces(self) -> Result<(), WireCtlError> {
-        self.wgapi.del_interface(&self.ifname).await
+        self.wgapi
+            .del_interface(&self.ifname, self.netns(), &self.timeouts())
+            .await
     }
 
     pub fn ifname(&self) -> &str {
@@ -0,0 +1,212 @@
+//! Tunnel address management
+//!
+//! [`Ipam`] hands out single addresses (`/32` and `/128`) from address pools,
+//! skipping the addresses already assigned to peers and the reserved ones.
+use crate::{allowedips::normalize, types::*};
+use ipnetwork::IpNetwork;
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Allocator of tunnel addresses
+///
+/// Allocation is deterministic: the lowest free address of the first pool of
+/// the family which has one is returned. The network address of a pool is never
+/// allocated, nor the broadcast address of an IPv4 pool.
+#[derive(Clone, Debug, Default)]
+pub struct Ipam {
+    pools: Vec<IpNetwork>,
+    /// Allowed IPs of the peers, which can't be released
+    assigned: Vec<IpNetwork>,
+    reserved: BTreeSet<IpNetwork>,
+}
+
+impl Ipam {
+    pub fn new(pools: &[IpNetwork]) -> Self {
+        Self {
+            pools: pools.iter().map(normalize).collect(),
+            assigned: Vec::new(),
+            reserved: BTreeSet::new(),
+        }
+    }
+
+    /// Mark the allowed IPs of the peers of `device` as used
+    ///
+    /// Allowed IPs which are not inside a pool, such as a default route, are
+    /// ignored.
+    pub fn add_device(&mut self, device: &WgDevice) {
+        for peer in &device.peers {
+            for allowed_ip in &peer.allow_ips {
+                let allowed_ip = normalize(allowed_ip);
+                if self.pools.iter().any(|pool| inside(&allowed_ip, pool)) {
+                    self.assigned.push(allowed_ip);
+                }
+            }
+        }
+    }
+
+    pub fn with_device(mut self, device: &WgDevice) -> Self {
+        self.add_device(device);
+        self
+    }
+
+    /// Reserve `network`, so that none of its addresses is allocated
+    ///
+    /// Returns `false` if it was already reserved.
+    pub fn reserve(&mut self, network: IpNetwork) -> bool {
+        self.reserved.insert(normalize(&network))
+    }
+
+    /// Release a reservation or an allocated address
+    ///
+    /// Returns `false` if `network` was not reserved. The allowed IPs of the
+    /// peers stay in use.
+    pub fn release(&mut self, network: &IpNetwork) -> bool {
+        self.reserved.remove(&normalize(network))
+    }
+
+    /// Reserved and allocated networks, for storing them
+    pub fn reservations(&self) -> impl Iterator<Item = &IpNetwork> {
+        self.reserved.iter()
+    }
+
+    /// Whether `addr` is inside a pool, and neither assigned nor reserved
+    pub fn is_free(&self, addr: IpAddr) -> bool {
+        let (ipv6, value) = to_u128(addr);
+        self.pools
+            .iter()
+            .filter(|pool| pool.is_ipv6() == ipv6)
+            .map(usable_range)
+            .any(|(start, end)| start <= value && value <= end)
+            && !self.used().any(|net| net.contains(addr))
+    }
+
+    /// The address [`Ipam::allocate_v4()`] would return
+    pub fn next_free_v4(&self) -> Option<IpNetwork> {
+        self.next_free(false)
+    }
+
+    /// The address [`Ipam::allocate_v6()`] would return
+    pub fn next_free_v6(&self) -> Option<IpNetwork> {
+        self.next_free(true)
+    }
+
+    /// Reserve and return the next free IPv4 address as a `/32`
+    pub fn allocate_v4(&mut self) -> Option<IpNetwork> {
+        let network = self.next_free_v4()?;
+        self.reserved.insert(network);
+        Some(network)
+    }
+
+    /// Reserve and return the next free IPv6 address as a `/128`
+    pub fn allocate_v6(&mut self) -> Option<IpNetwork> {
+        let network = self.next_free_v6()?;
+        self.reserved.insert(network);
+        Some(network)
+    }
+
+    /// Allocate an address of every family which has a pool
+    ///
+    /// Nothing is allocated if a family has no free address left.
+    pub fn allocate(&mut self) -> Option<Vec<IpNetwork>> {
+        let has_v4 = self.pools.iter().any(IpNetwork::is_ipv4);
+        let has_v6 = self.pools.iter().any(IpNetwork::is_ipv6);
+
+        let mut networks = Vec::with_capacity(2);
+        if has_v4 {
+            networks.push(self.next_free_v4()?);
+        }
+        if has_v6 {
+            networks.push(self.next_free_v6()?);
+        }
+        self.reserved.extend(networks.iter().copied());
+        Some(networks)
+    }
+
+    fn used(&self) -> impl Iterator<Item = &IpNetwork> {
+        self.assigned.iter().chain(self.reserved.iter())
+    }
+
+    fn next_free(&self, ipv6: bool) -> Option<IpNetwork> {
+        let mut used: Vec<(u128, u128)> = self
+            .used()
+            .filter(|net| net.is_ipv6() == ipv6)
+            .map(|net| {
+                let (_, start) = to_u128(net.network());
+                (start, start | host_mask(width(net) - net.prefix()))
+            })
+            .collect();
+        used.sort_unstable();
+
+        for pool in self.pools.iter().filter(|pool| pool.is_ipv6() == ipv6) {
+            let (start, end) = usable_range(pool);
+            let mut candidate = Some(start);
+            for &(used_start, used_end) in &used {
+                match candidate {
+                    Some(addr) if used_start > addr => break,
+                    Some(addr) if used_end >= addr => candidate = used_end.checked_add(1),
+                    Some(_) => (),
+                    None => break,
+                }
+            }
+
+            if let Some(candidate) = candidate.filter(|&addr| addr <= end) {
+                let addr: IpAddr = if ipv6 {
+                    Ipv6Addr::from(candidate).into()
+                } else {
+                    Ipv4Addr::from(candidate as u32).into()
+                };
+                // A host prefix always fits the address
+                return Some(IpNetwork::new(addr, if ipv6 { 128 } else { 32 }).unwrap());
+            }
+        }
+        None
+    }
+}
+
+fn width(net: &IpNetwork) -> u8 {
+    if net.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+fn host_mask(host_bits: u8) -> u128 {
+    if host_bits >= 128 {
+        u128::MAX
+    } else {
+        (1 << host_bits) - 1
+    }
+}
+
+/// The address as a number, with whether it is IPv6
+fn to_u128(addr: IpAddr) -> (bool, u128) {
+    match addr {
+        IpAddr::V4(addr) => (false, u32::from(addr) as u128),
+        IpAddr::V6(addr) => (true, u128::from(addr)),
+    }
+}
+
+/// The first and last allocatable addresses of `pool`
+fn usable_range(pool: &IpNetwork) -> (u128, u128) {
+    let (ipv6, network) = to_u128(pool.network());
+    let host_bits = width(pool) - pool.prefix();
+    let last = network | host_mask(host_bits);
+
+    match (ipv6, host_bits) {
+        // Point-to-point and single address pools have no network address
+        (_, 0) | (false, 1) => (network, last),
+        (false, _) => (network + 1, last - 1),
+        (true, _) => (network + 1, last),
+    }
+}
+
+/// Whether `net` is inside `pool`
+fn inside(net: &IpNetwork, pool: &IpNetwork) -> bool {
+    net.is_ipv4() == pool.is_ipv4() && net.prefix() >= pool.prefix() && pool.contains(net.network())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use super::*;
+
+fn net(s: &str) -> IpNetwork {
+    s.parse().unwrap()
+}
+
+fn peer(pubkey: u8, allowed_ips: &[&str]) -> Peer {
+    let mut peer = Peer::new(PublicKey::from([pubkey; WG_KEY_LEN]));
+    peer.allow_ips = allowed_ips.iter().map(|ip| net(ip)).collect();
+    peer
+}
+
+#[test]
+fn ipam_skips_used_addresses() {
+    let mut device = WgDevice::new("test");
+    device.peers = vec![
+        peer(1, &["10.8.0.2/32", "fd08::2/128", "0.0.0.0/0"]),
+        peer(2, &["10.8.0.4/31"]),
+    ];
+    let mut ipam = Ipam::new(&[net("10.8.0.0/16"), net("fd08::/64")]).with_device(&device);
+    // The address of the server itself
+    assert!(ipam.reserve(net("10.8.0.1/32")));
+    assert!(!ipam.reserve(net("10.8.0.1/32")));
+
+    assert_eq!(ipam.allocate_v4(), Some(net("10.8.0.3/32")));
+    assert_eq!(ipam.allocate_v4(), Some(net("10.8.0.6/32")));
+    assert_eq!(ipam.next_free_v6(), Some(net("fd08::1/128")));
+    assert_eq!(
+        ipam.allocate(),
+        Some(vec![net("10.8.0.7/32"), net("fd08::1/128")])
+    );
+    assert_eq!(ipam.allocate_v6(), Some(net("fd08::3/128")));
+
+    assert!(!ipam.is_free("10.8.0.5".parse().unwrap()));
+    assert!(!ipam.is_free("10.8.0.0".parse().unwrap()));
+    assert!(!ipam.is_free("10.9.0.1".parse().unwrap()));
+    assert!(ipam.is_free("10.8.0.8".parse().unwrap()));
+
+    // Released addresses are allocated again, peers' addresses can't be released
+    assert!(ipam.release(&net("10.8.0.3/32")));
+    assert!(!ipam.release(&net("10.8.0.2/32")));
+    assert_eq!(ipam.allocate_v4(), Some(net("10.8.0.3/32")));
+    assert_eq!(
+        ipam.reservations().copied().collect::<Vec<_>>(),
+        vec![
+            net("10.8.0.1/32"),
+            net("10.8.0.3/32"),
+            net("10.8.0.6/32"),
+            net("10.8.0.7/32"),
+            net("fd08::1/128"),
+            net("fd08::3/128"),
+        ]
+    );
+}
+
+#[test]
+fn ipam_exhausted() {
+    let mut ipam = Ipam::new(&[net("192.0.2.0/30"), net("192.0.2.8/31")]);
+
+    assert_eq!(ipam.allocate_v4(), Some(net("192.0.2.1/32")));
+    assert_eq!(ipam.allocate_v4(), Some(net("192.0.2.2/32")));
+    assert_eq!(ipam.allocate_v4(), Some(net("192.0.2.8/32")));
+    assert_eq!(ipam.allocate_v4(), Some(net("192.0.2.9/32")));
+    assert_eq!(ipam.allocate_v4(), None);
+    // There is no IPv6 pool
+    assert_eq!(ipam.allocate_v6(), None);
+    assert_eq!(ipam.allocate(), None);
+
+    // A family without a free address allocates nothing
+    let mut ipam = Ipam::new(&[net("192.0.2.1/32"), net("fd00::/120")]);
+    ipam.reserve(net("192.0.2.1/32"));
+    assert_eq!(ipam.allocate(), None);
+    assert_eq!(ipam.next_free_v6(), Some(net("fd00::1/128")));
+}
@@ -4,7 +4,19 @@
 //! It communicates with programs such as `wireguard-go` by unix domain socket.
 //!
 //! For more detail protocol definition, read the [documentation](https://www.wireguard.com/xplatform/) by wireguard.
-use crate::{implementations::WgImpl, types::*, WireCtlError};
+//!
+//! Every request opens its own connection, which is never reused. A request is
+//! fully serialized before the first byte is written, so dropping a future in
+//! the middle of an operation only closes its connection, and can't leave a
+//! half-written request in front of the next one.
+use crate::{
+    allowedips::normalize,
+    implementations::WgImpl,
+    netns::{self, NetNs},
+    timeout::{default_timeouts, with_timeout, TimeoutPhase, Timeouts},
+    types::*,
+    WireCtlError,
+};
 use async_fs::{read_dir, remove_file};
 use async_net::unix::UnixStream;
 use async_process::Command;
@@ -16,8 +28,9 @@ use std::{
     env,
     ffi::OsStr,
     io::{Error, ErrorKind},
-    os::unix::fs::FileTypeExt,
-    path::PathBuf,
+    net::SocketAddr,
+    os::unix::{fs::FileTypeExt, net::UnixStream as StdUnixStream},
+    path::{Path, PathBuf},
     str::FromStr,
     time::Duration,
     time::SystemTime,
@@ -40,6 +53,11 @@ static WG_USERSPACE_EXEC: Lazy<Cow<OsStr>> = Lazy::new(|| {
     exec
 });
 
+/// Userspace implementations with the default deadlines
+///
+/// The crate itself calls the functions of this module with explicit
+/// deadlines instead.
+#[allow(dead_code)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Ipc;
 
@@ -49,51 +67,14 @@ impl WgImpl for Ipc {
     where
         S: AsRef<OsStr> + ?Sized + Send + Sync,
     {
-        let program: &OsStr = WG_USERSPACE_EXEC.as_ref();
-        let status = Command::new(program)
-            .arg(ifname)
-            .env_clear()
-            .status()
-            .await?;
-
-        if !status.success() {
-            return Err(WireCtlError::UserspaceLaunch(status));
-        }
-
-        Ok(())
+        create_interface(ifname, None, &default_timeouts()).await
     }
 
     async fn list_interfaces() -> Result<Vec<String>, WireCtlError> {
-        let mut sockdir = match read_dir(WG_SOCKET_PATH).await {
-            Ok(data) => data,
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    return Ok(Vec::new());
-                }
-                return Err(e.into());
-            }
-        };
-
-        let mut interfaces = Vec::new();
-        while let Some(entry) = sockdir.try_next().await? {
-            let meta = entry.metadata().await?;
-            if meta.file_type().is_socket() {
-                let sockname = PathBuf::from(entry.file_name());
-                if sockname.extension() != Some(OsStr::new(WG_SOCKET_SUFFIX)) {
-                    continue;
-                }
-
-                let ifname = sockname.file_stem().unwrap();
-                if check_device(ifname).await.is_ok() {
-                    interfaces.push(ifname.to_string_lossy().into_owned());
-                }
-            }
-        }
-
-        Ok(interfaces)
+        list_interfaces(&default_timeouts()).await
     }
 
-    async fn remove_interface<S>(ifname: &S) -> Result<(), WireCtlError>
+    async fn remove_interface<S>(_ifname: &S) -> Result<(), WireCtlError>
     where
         S: AsRef<OsStr> + ?Sized + Send + Sync,
     {
@@ -104,73 +85,123 @@ impl WgImpl for Ipc {
     where
         S: AsRef<OsStr> + ?Sized + Send + Sync,
     {
-        let rslt = open_device(ifname).await;
-        rslt.map(|_| ())
+        check_device(ifname, None, &default_timeouts()).await
     }
 
     async fn get_config<S>(ifname: &S) -> Result<WgDevice, WireCtlError>
     where
         S: AsRef<OsStr> + ?Sized + Send + Sync,
     {
-        let mut ctrl_sock = BufReader::new(open_device(ifname).await?);
-        ctrl_sock.write_all(b"get=1\n\n").await?;
-
-        parse_device_config(&mut ctrl_sock, ifname).await
+        get_config(ifname, None, &default_timeouts()).await
     }
 
     async fn set_config<S>(ifname: &S, conf: WgDeviceSetter) -> Result<(), WireCtlError>
     where
         S: AsRef<OsStr> + ?Sized + Send + Sync,
     {
-        let mut ctrl_sock = BufReader::new(open_device(ifname).await?);
+        set_config(ifname, conf, None, &default_timeouts()).await
+    }
+}
 
-        emit_device_config(&mut ctrl_sock, conf).await?;
-        ctrl_sock.flush().await?;
+/// Launch the userspace implementation for `ifname`, inside `netns` if given
+///
+/// The launcher daemonizes once the interface is set up, and has the
+/// [`TimeoutPhase::Connect`] deadline to do so.
+///
+/// Its tunnel device is created in `netns`, while its control socket file stays
+/// in [`WG_SOCKET_PATH`] of the current filesystem.
+pub async fn create_interface<S>(
+    ifname: &S,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError>
+where
+    S: AsRef<OsStr> + ?Sized + Send + Sync,
+{
+    let program: &OsStr = WG_USERSPACE_EXEC.as_ref();
+    let mut child = netns::run_in(netns, || {
+        Command::new(program).arg(ifname).env_clear().spawn()
+    })??;
+    let status = match with_timeout(timeouts, TimeoutPhase::Connect, async {
+        Ok(child.status().await?)
+    })
+    .await
+    {
+        Ok(status) => status,
+        Err(e) => {
+            // Don't leave a stuck launcher behind, nor a zombie once killed
+            child.kill().ok();
+            child.status().await.ok();
+            return Err(e);
+        }
+    };
 
-        let mut curr_line = String::new();
+    if !status.success() {
+        return Err(WireCtlError::UserspaceLaunch(status));
+    }
 
-        // Read return errno
-        // Format:
-        // `errno=0`
-        ctrl_sock.read_line(&mut curr_line).await?;
-        let line = curr_line.trim_end();
-        let (key, value) = line.split_once('=').ok_or(WireCtlError::InvalidProtocol)?;
-        let errno = if key == "errno" {
-            value.parse::<i32>()?
-        } else {
-            return Err(WireCtlError::InvalidProtocol);
-        };
+    Ok(())
+}
 
-        // Next line should be empty
-        curr_line.clear();
-        ctrl_sock.read_line(&mut curr_line).await?;
-        let line = curr_line.trim_end();
-        if !line.is_empty() {
-            return Err(WireCtlError::InvalidProtocol);
+pub async fn list_interfaces(timeouts: &Timeouts) -> Result<Vec<String>, WireCtlError> {
+    let mut sockdir = match read_dir(WG_SOCKET_PATH).await {
+        Ok(data) => data,
+        Err(e) => {
+            if e.kind() == ErrorKind::NotFound {
+                return Ok(Vec::new());
+            }
+            return Err(e.into());
         }
+    };
 
-        if errno == 0 {
-            Ok(())
-        } else {
-            Err(WireCtlError::DeviceError(errno))
+    let mut interfaces = Vec::new();
+    while let Some(entry) = sockdir.try_next().await? {
+        let meta = entry.metadata().await?;
+        if meta.file_type().is_socket() {
+            let Some(ifname) = socket_ifname(&entry.file_name()) else {
+                continue;
+            };
+            if check_device(&ifname, None, timeouts).await.is_ok() {
+                interfaces.push(ifname);
+            }
         }
     }
-}
 
-pub async fn create_interface(ifname: &str) -> Result<(), WireCtlError> {
-    Ipc::create_interface(ifname).await
+    Ok(interfaces)
 }
 
-pub async fn list_interfaces() -> Result<Vec<String>, WireCtlError> {
-    Ipc::list_interfaces().await
+/// The interface served by the socket named `file_name` in [`WG_SOCKET_PATH`]
+pub(crate) fn socket_ifname(file_name: &OsStr) -> Option<String> {
+    let sockname = Path::new(file_name);
+    if sockname.extension() != Some(OsStr::new(WG_SOCKET_SUFFIX)) {
+        return None;
+    }
+    Some(sockname.file_stem()?.to_string_lossy().into_owned())
 }
 
-async fn open_device<S: AsRef<OsStr> + ?Sized>(ifname: &S) -> Result<UnixStream, WireCtlError> {
+/// Connect to the socket of `ifname`, from inside `netns` if given
+async fn open_device<S: AsRef<OsStr> + ?Sized>(
+    ifname: &S,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<UnixStream, WireCtlError> {
     let mut socket_path = PathBuf::from_str(WG_SOCKET_PATH).unwrap();
     socket_path.push(ifname.as_ref());
     socket_path.set_extension(WG_SOCKET_SUFFIX);
 
-    let socket = match UnixStream::connect(&socket_path).await {
+    let connect = with_timeout(timeouts, TimeoutPhase::Connect, async {
+        let Some(netns) = netns.cloned() else {
+            return Ok(UnixStream::connect(&socket_path).await);
+        };
+        // The connection may block, so the thread entering the namespace is
+        // one of the blocking pool rather than one running futures
+        let path = socket_path.clone();
+        let socket =
+            smol::unblock(move || netns::run_in(Some(&netns), || StdUnixStream::connect(&path)))
+                .await?;
+        Ok(socket.and_then(UnixStream::try_from))
+    });
+    let socket = match connect.await? {
         Ok(s) => s,
         Err(e) => {
             // Try to clean up the unused socket
@@ -186,15 +217,55 @@ async fn open_device<S: AsRef<OsStr> + ?Sized>(ifname: &S) -> Result<UnixStream,
     Ok(socket)
 }
 
-pub async fn check_device<S>(ifname: &S) -> Result<(), WireCtlError>
+pub async fn check_device<S>(
+    ifname: &S,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError>
 where
     S: AsRef<OsStr> + ?Sized + Send + Sync,
 {
-    Ipc::check_device(ifname).await
+    let rslt = open_device(ifname, netns, timeouts).await;
+    rslt.map(|_| ())
 }
 
-pub async fn get_config(ifname: &str) -> Result<WgDevice, WireCtlError> {
-    Ipc::get_config(ifname).await
+/// The configuration of `ifname`, read from its socket in [`WG_SOCKET_PATH`]
+///
+/// The connection is opened from inside `netns`, the namespace of the link,
+/// if given.
+pub async fn get_config<S>(
+    ifname: &S,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<WgDevice, WireCtlError>
+where
+    S: AsRef<OsStr> + ?Sized + Send + Sync,
+{
+    let mut ctrl_sock = BufReader::new(open_device(ifname, netns, timeouts).await?);
+    send_request(&mut ctrl_sock, b"get=1\n\n", timeouts).await?;
+
+    with_timeout(
+        timeouts,
+        TimeoutPhase::Read,
+        parse_device_config(&mut ctrl_sock, ifname),
+    )
+    .await
+}
+
+async fn send_request<S>(
+    ctrl_sock: &mut S,
+    request: &[u8],
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError>
+where
+    S: AsyncWrite + Unpin + ?Sized,
+{
+    with_timeout(timeouts, TimeoutPhase::Write, async {
+        ctrl_sock.write_all(request).await?;
+        ctrl_sock.flush().await?;
+        Ok(())
+    })
+    .await
 }
 
 async fn parse_device_config<R, S>(ctrl_sock: &mut R, ifname: &S) -> Result<WgDevice, WireCtlError>
@@ -224,7 +295,10 @@ where
             "listen_port" => {
                 device.listen_port = value.parse()?;
             }
-            "fwmark" => device.fwmark = value.parse()?,
+            "fwmark" => {
+                let fwmark = value.parse()?;
+                device.fwmark = Some(fwmark).filter(|&m| m != 0);
+            }
             "public_key" => {
                 let pubkey = PublicKey::from_hex(value)?;
                 let peer = parse_peer_config(ctrl_sock, &mut curr_line, pubkey).await?;
@@ -285,13 +359,17 @@ where
         let (key, value) = line.split_once('=').ok_or(WireCtlError::InvalidProtocol)?;
 
         match key {
-            "preshared_key" => peer.preshared_key = PresharedKey::from_hex(value)?,
+            "preshared_key" => {
+                let psk = PresharedKey::from_hex(value)?;
+                peer.preshared_key = Some(psk).filter(|k| !k.is_empty());
+            }
             "allowed_ip" => {
                 let allowed_ip = value.parse()?;
                 peer.allow_ips.push(allowed_ip);
             }
             "endpoint" => {
-                peer.endpoint = value.parse()?;
+                let endpoint: SocketAddr = value.parse()?;
+                peer.endpoint = Some(endpoint).filter(|e| !e.ip().is_unspecified());
             }
             "tx_bytes" => {
                 peer.tx_bytes = value.parse()?;
@@ -300,7 +378,8 @@ where
                 peer.rx_bytes = value.parse()?;
             }
             "persistent_keepalive_interval" => {
-                peer.persistent_keepalive = value.parse()?;
+                let keepalive = value.parse()?;
+                peer.persistent_keepalive = Some(keepalive).filter(|&k| k != 0);
             }
             "last_handshake_time_sec" => {
                 last_handshake_s = Duration::from_secs(value.parse()?);
@@ -317,12 +396,71 @@ where
         ctrl_sock.read_line(curr_line).await?;
     }
 
-    peer.last_handshake = SystemTime::UNIX_EPOCH + last_handshake_s + last_handshake_ns;
+    // The kernel and wireguard-go report zero if no handshake has been completed
+    let last_handshake = last_handshake_s + last_handshake_ns;
+    if !last_handshake.is_zero() {
+        peer.last_handshake = Some(SystemTime::UNIX_EPOCH + last_handshake);
+    }
     Ok(peer)
 }
 
-pub async fn set_config(ifname: &str, conf: WgDeviceSetter) -> Result<(), WireCtlError> {
-    Ipc::set_config(ifname, conf).await
+/// Apply `conf` to `ifname` through its socket, connected from inside `netns`
+/// as for [`get_config()`]
+pub async fn set_config<S>(
+    ifname: &S,
+    conf: WgDeviceSetter,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError>
+where
+    S: AsRef<OsStr> + ?Sized + Send + Sync,
+{
+    // Serialize the whole request before touching the socket
+    let mut request = Vec::new();
+    emit_device_config(&mut request, conf).await?;
+
+    let mut ctrl_sock = BufReader::new(open_device(ifname, netns, timeouts).await?);
+    send_request(&mut ctrl_sock, &request, timeouts).await?;
+
+    with_timeout(
+        timeouts,
+        TimeoutPhase::Read,
+        parse_set_response(&mut ctrl_sock),
+    )
+    .await
+}
+
+async fn parse_set_response<R>(ctrl_sock: &mut R) -> Result<(), WireCtlError>
+where
+    R: AsyncBufRead + AsyncRead + Unpin + ?Sized,
+{
+    let mut curr_line = String::new();
+
+    // Read return errno
+    // Format:
+    // `errno=0`
+    ctrl_sock.read_line(&mut curr_line).await?;
+    let line = curr_line.trim_end();
+    let (key, value) = line.split_once('=').ok_or(WireCtlError::InvalidProtocol)?;
+    let errno = if key == "errno" {
+        value.parse::<i32>()?
+    } else {
+        return Err(WireCtlError::InvalidProtocol);
+    };
+
+    // Next line should be empty
+    curr_line.clear();
+    ctrl_sock.read_line(&mut curr_line).await?;
+    let line = curr_line.trim_end();
+    if !line.is_empty() {
+        return Err(WireCtlError::InvalidProtocol);
+    }
+
+    if errno == 0 {
+        Ok(())
+    } else {
+        Err(WireCtlError::DeviceError(errno))
+    }
 }
 
 async fn emit_device_config<S>(ctrl_sock: &mut S, conf: WgDeviceSetter) -> Result<(), WireCtlError>
@@ -384,7 +522,14 @@ where
     if conf.replace_allowed_ips {
         ctrl_sock.write_all(b"replace_allowed_ips=true\n").await?;
     }
-    for allowed_ip in conf.allowed_ips {
+    // Wireguard ignores the host bits, so entries differing only by them are duplicates
+    let mut allowed_ips = Vec::with_capacity(conf.allowed_ips.len());
+    for allowed_ip in conf.allowed_ips.iter().map(normalize) {
+        if !allowed_ips.contains(&allowed_ip) {
+            allowed_ips.push(allowed_ip);
+        }
+    }
+    for allowed_ip in allowed_ips {
         let line = format!("allowed_ip={}\n", allowed_ip);
         ctrl_sock.write_all(line.as_bytes()).await?;
     }
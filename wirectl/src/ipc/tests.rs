@@ -59,6 +59,7 @@ fn cidr(a: u8, b: u8, c: u8, d: u8, mask: u8) -> IpNetwork {
 }
 
 #[test]
+#[allow(deprecated)]
 fn ipc_parse_1() {
     block_on(async {
         let mut stream = Cursor::new(IPC_GET_TESTDATA1.as_bytes());
@@ -81,7 +82,7 @@ fn ipc_parse_1() {
         assert_eq!(device.peers[0].allow_ips, &[cidr(192, 168, 4, 4, 32)]);
         assert_eq!(
             device.peers[0].endpoint,
-            "[abcd:23::33%2]:51820".parse().unwrap()
+            Some("[abcd:23::33%2]:51820".parse().unwrap())
         );
 
         assert_eq!(
@@ -91,10 +92,11 @@ fn ipc_parse_1() {
         assert_eq!(device.peers[1].tx_bytes, 38333);
         assert_eq!(device.peers[1].rx_bytes, 2224);
         assert_eq!(device.peers[1].allow_ips, &[cidr(192, 168, 4, 6, 32)]);
-        assert_eq!(device.peers[1].persistent_keepalive, 111);
+        assert_eq!(device.peers[1].persistent_keepalive, Some(111));
+        assert!(device.peers[1].preshared_key.is_none());
         assert_eq!(
             device.peers[1].endpoint,
-            "182.122.22.19:3233".parse().unwrap()
+            Some("182.122.22.19:3233".parse().unwrap())
         );
 
         assert_eq!(
@@ -109,11 +111,41 @@ fn ipc_parse_1() {
         );
         assert_eq!(
             device.peers[2].endpoint,
-            "5.152.198.39:51820".parse().unwrap()
+            Some("5.152.198.39:51820".parse().unwrap())
         );
     })
 }
 
+#[test]
+fn ipc_parse_unset() {
+    // wireguard-go reports unset values as zeros
+    const GET: &str = "private_key=e84b5a6d2717c1003a13b431570353dbaca9146cf150c5f8575680feba52027a
+listen_port=12912
+fwmark=0
+public_key=b85996fecc9c7f1fc6d2572a76eda11d59bcd20be8e543b15ce4bd85a8e75a33
+preshared_key=0000000000000000000000000000000000000000000000000000000000000000
+protocol_version=1
+last_handshake_time_sec=0
+last_handshake_time_nsec=0
+tx_bytes=0
+rx_bytes=0
+persistent_keepalive_interval=0
+errno=0
+
+";
+    block_on(async {
+        let mut stream = Cursor::new(GET.as_bytes());
+        let device = parse_device_config(&mut stream, "test").await.unwrap();
+
+        assert_eq!(device.fwmark, None);
+        let peer = &device.peers[0];
+        assert!(peer.preshared_key.is_none());
+        assert_eq!(peer.endpoint, None);
+        assert_eq!(peer.last_handshake, None);
+        assert_eq!(peer.persistent_keepalive, None);
+    })
+}
+
 #[test]
 fn ipc_emit_1() {
     block_on(async {
@@ -205,3 +237,112 @@ fn ipc_emit_1() {
         );
     });
 }
+
+/// A control socket whose peer never answers
+struct WedgedSocket;
+
+impl AsyncRead for WedgedSocket {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        _buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::task::Poll::Pending
+    }
+}
+
+#[test]
+fn ipc_parse_timeout() {
+    smol::block_on(async {
+        let mut stream = BufReader::new(WedgedSocket);
+        let timeouts = Timeouts::none().set_read(Duration::from_millis(50));
+
+        let rslt = with_timeout(
+            &timeouts,
+            TimeoutPhase::Read,
+            parse_device_config(&mut stream, "test"),
+        )
+        .await;
+
+        assert!(matches!(
+            rslt,
+            Err(WireCtlError::Timeout(TimeoutPhase::Read))
+        ));
+    })
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn ipc_emit_from_json() {
+    block_on(async {
+        let settings: WgDeviceSetter = serde_json::from_str(
+            r#"{
+                "device_name": "test",
+                "private_key": "6EtabScXwQA6E7QxVwNT26ypFGzxUMX4V1aA/rpSAno=",
+                "listen_port": 12912,
+                "replace_peers": true,
+                "peers": [
+                    {
+                        "public_key": "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=",
+                        "endpoint": "182.122.22.19:3233",
+                        "replace_allowed_ips": true,
+                        "allowed_ips": ["192.168.4.4/32"]
+                    },
+                    {
+                        "public_key": "6Bi1jbUnQIf8wb5dxyjPU9O1cmtM72ubq4+PjCRSwlw=",
+                        "remove": true
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut stream = Cursor::new(Vec::new());
+        emit_device_config(&mut stream, settings).await.unwrap();
+
+        assert_eq!(
+            String::from_utf8(stream.into_inner()).unwrap(),
+            "set=1
+private_key=e84b5a6d2717c1003a13b431570353dbaca9146cf150c5f8575680feba52027a
+listen_port=12912
+replace_peers=true
+public_key=b85996fecc9c7f1fc6d2572a76eda11d59bcd20be8e543b15ce4bd85a8e75a33
+endpoint=182.122.22.19:3233
+replace_allowed_ips=true
+allowed_ip=192.168.4.4/32
+public_key=e818b58db5274087fcc1be5dc728cf53d3b5726b4cef6b9bab8f8f8c2452c25c
+remove=true
+
+"
+        );
+
+        // Unknown fields are rejected
+        assert!(serde_json::from_str::<WgDeviceSetter>(
+            r#"{"device_name": "test", "listen_prot": 1}"#
+        )
+        .is_err());
+    });
+}
+
+#[test]
+fn ipc_emit_normalized_allowed_ips() {
+    block_on(async {
+        let mut stream = Cursor::new(Vec::new());
+        let peer = PeerSetter::new(
+            PublicKey::from_hex("58402e695ba1772b1cc9309755f043251ea77fdcf10fbe63989ceb7e19321376")
+                .unwrap(),
+        )
+        .add_allowed_ip(cidr(192, 168, 4, 6, 24))
+        .add_allowed_ip(cidr(192, 168, 4, 0, 24))
+        .add_allowed_ip(cidr(10, 0, 0, 1, 32));
+
+        emit_peer_config(&mut stream, peer).await.unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&stream.into_inner()).unwrap(),
+            "public_key=58402e695ba1772b1cc9309755f043251ea77fdcf10fbe63989ceb7e19321376
+allowed_ip=192.168.4.0/24
+allowed_ip=10.0.0.1/32
+"
+        );
+    })
+}
@@ -0,0 +1,61 @@
+use serde_json::Value;
+use std::env;
+use std::io::{stdout, Write};
+use std::time::UNIX_EPOCH;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use wirectl::types::WgDevice;
+use wirectl::WireCtlError;
+
+/// The serde representation of `device`, with the handshakes as RFC 3339 and
+/// unix seconds
+///
+/// Private and preshared keys are left out unless `show_keys`.
+fn device_json(device: &WgDevice, show_keys: bool) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(device)?;
+    if let (false, Some(object)) = (show_keys, value.as_object_mut()) {
+        object.remove("private_key");
+    }
+
+    let json_peers = value["peers"].as_array_mut().into_iter().flatten();
+    for (json, peer) in json_peers.zip(&device.peers) {
+        if let (false, Some(object)) = (show_keys, json.as_object_mut()) {
+            object.remove("preshared_key");
+        }
+        let handshake = peer.last_handshake;
+        json["last_handshake"] = serde_json::to_value(
+            handshake.and_then(|t| OffsetDateTime::from(t).format(&Rfc3339).ok()),
+        )?;
+        json["last_handshake_unix"] = serde_json::to_value(
+            handshake
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        )?;
+    }
+    Ok(value)
+}
+
+/// Print the devices as JSON
+///
+/// A single device is printed as an object, otherwise as an array.
+/// Private and preshared keys are omitted unless `WG_HIDE_KEYS=never`.
+pub fn print_devices(devices: &[WgDevice], single: bool) -> Result<(), WireCtlError> {
+    let show_keys = matches!(env::var("WG_HIDE_KEYS").as_deref(), Ok("never"));
+    let devices = devices
+        .iter()
+        .map(|device| device_json(device, show_keys))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(std::io::Error::from)?;
+
+    let mut out = stdout().lock();
+    if single && devices.len() == 1 {
+        serde_json::to_writer_pretty(&mut out, &devices[0]).map_err(std::io::Error::from)?;
+    } else {
+        serde_json::to_writer_pretty(&mut out, &devices).map_err(std::io::Error::from)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
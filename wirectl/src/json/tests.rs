@@ -0,0 +1,66 @@
+use super::*;
+use std::time::Duration;
+use wirectl::types::{Peer, PresharedKey, PrivateKey, PublicKey, WG_KEY_LEN};
+
+fn device() -> WgDevice {
+    let private_key = PrivateKey::from([1; WG_KEY_LEN]);
+    let mut device = WgDevice::new("wg0");
+    device.ifindex = 7;
+    device.public_key = Some(private_key.public_key());
+    device.private_key = Some(private_key);
+    device.listen_port = 51820;
+
+    let mut peer = Peer::new(PublicKey::from([2; WG_KEY_LEN]));
+    peer.preshared_key = Some(PresharedKey::from([3; WG_KEY_LEN]));
+    peer.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+    peer.last_handshake = Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    peer.allow_ips = vec!["10.0.0.2/32".parse().unwrap()];
+    device.peers.push(peer);
+    device
+        .peers
+        .push(Peer::new(PublicKey::from([4; WG_KEY_LEN])));
+    device
+}
+
+#[test]
+fn json_hidden_keys() {
+    let device = device();
+    let value = device_json(&device, false).unwrap();
+    assert_eq!(value["device_name"], "wg0");
+    assert_eq!(value["ifindex"], 7);
+    assert_eq!(value["listen_port"], 51820);
+    assert!(value.get("private_key").is_none());
+    assert_eq!(
+        value["public_key"],
+        device.public_key.as_ref().unwrap().to_base64()
+    );
+
+    let peer = &value["peers"][0];
+    assert_eq!(
+        peer["public_key"],
+        PublicKey::from([2; WG_KEY_LEN]).to_base64()
+    );
+    assert!(peer.get("preshared_key").is_none());
+    assert_eq!(peer["endpoint"], "192.0.2.1:51820");
+    assert_eq!(peer["allow_ips"][0], "10.0.0.2/32");
+    assert_eq!(peer["last_handshake"], "2020-09-13T12:26:40Z");
+    assert_eq!(peer["last_handshake_unix"], 1_600_000_000);
+
+    let peer = &value["peers"][1];
+    assert!(peer.get("preshared_key").is_none());
+    assert!(peer["last_handshake"].is_null());
+    assert!(peer["last_handshake_unix"].is_null());
+}
+
+#[test]
+fn json_shown_keys() {
+    let value = device_json(&device(), true).unwrap();
+    assert_eq!(
+        value["private_key"],
+        PrivateKey::from([1; WG_KEY_LEN]).to_base64()
+    );
+    assert_eq!(
+        value["peers"][0]["preshared_key"],
+        PresharedKey::from([3; WG_KEY_LEN]).to_base64()
+    );
+}
@@ -9,11 +9,23 @@ mod api;
 
 mod error;
 
-pub mod interface;
+pub mod acl;
+pub mod allowedips;
+pub mod events;
 pub mod implementations;
+pub mod interface;
+pub mod ipam;
+pub mod netlink;
+pub mod netns;
+pub mod portforward;
 
+pub mod shaping;
+pub mod stats;
+pub mod timeout;
+pub mod tunnel;
 pub mod types;
 
 mod ipc;
+mod nftables;
 
 pub use self::error::WireCtlError;
@@ -2,15 +2,21 @@ use clap::Parser;
 use smol::block_on;
 use std::env;
 use std::io::stdin;
+use std::net::ToSocketAddrs;
+use std::path::Path;
 use std::process::exit;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, UNIX_EPOCH};
 use time::OffsetDateTime;
+use wirectl::allowedips::find_conflicts_in_changes;
 use wirectl::interface::WgInterface;
-use wirectl::types::{PresharedKey, PrivateKey, PublicKey, WgDevice, WG_KEY_BASE64_LEN};
+use wirectl::stats::{StatsSnapshot, StatsWatcher};
+use wirectl::types::{PeerSetter, PresharedKey, PrivateKey, PublicKey, WgDevice, WgDeviceSetter};
 use wirectl::WireCtlError;
 use zeroize::Zeroizing;
 
 mod args;
+mod exporter;
+mod json;
 use args::*;
 
 fn main() {
@@ -29,7 +35,12 @@ fn main() {
                 exit(1);
             }
         }
-        SubCommands::Set(opt) => todo!(),
+        SubCommands::Set(opt) => {
+            if let Err(e) = block_on(cmd_set(&opt)) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
         SubCommands::Genkey => cmd_genkey(),
         SubCommands::Genpsk => cmd_genpsk(),
         SubCommands::Pubkey => {
@@ -38,6 +49,12 @@ fn main() {
                 exit(1);
             }
         }
+        SubCommands::Exporter(opt) => {
+            if let Err(e) = block_on(exporter::cmd_exporter(&opt)) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
     }
 }
 
@@ -69,7 +86,74 @@ async fn cmd_list() -> Result<(), WireCtlError> {
     Ok(())
 }
 
+async fn cmd_set(opt: &SetCmd) -> Result<(), WireCtlError> {
+    let wgif = WgInterface::get_interface(&opt.interface).await?;
+    let device = wgif.get_config().await?;
+
+    let mut conf = WgDeviceSetter::from(&device);
+    if let Some(path) = &opt.private_key {
+        let key = read_key_file(path)?;
+        conf = conf.set_private_key(PrivateKey::from_base64(key.trim())?);
+    }
+    if let Some(listen_port) = opt.listen_port {
+        conf = conf.set_listen_port(listen_port);
+    }
+    if let Some(fwmark) = opt.fwmark {
+        conf = conf.set_fwmark(fwmark);
+    }
+
+    for peer in &opt.peers {
+        let mut setter = PeerSetter::new(PublicKey::from_base64(&peer.public_key)?);
+        if peer.remove {
+            setter = setter.set_remove();
+        }
+        if let Some(path) = &peer.preshared_key {
+            // An empty file, such as /dev/null, removes the preshared key
+            let key = read_key_file(path)?;
+            let key = if key.trim().is_empty() {
+                PresharedKey::default()
+            } else {
+                PresharedKey::from_base64(key.trim())?
+            };
+            setter = setter.set_preshared_key(key);
+        }
+        if let Some(endpoint) = &peer.endpoint {
+            // Name resolution blocks, so it runs on the blocking thread pool
+            let endpoint = endpoint.clone();
+            let endpoint = smol::unblock(move || endpoint.to_socket_addrs().map(|mut a| a.next()))
+                .await?
+                .ok_or(WireCtlError::InvalidConfig)?;
+            setter = setter.set_endpoint(endpoint);
+        }
+        if let Some(keepalive) = peer.persistent_keepalive {
+            setter = setter.set_persistent_keepalive(keepalive);
+        }
+        if let Some(allowed_ips) = &peer.allowed_ips {
+            setter = setter
+                .set_replace_allowed_ips()
+                .add_allowed_ips(allowed_ips);
+        }
+        conf = conf.set_peer(setter);
+    }
+
+    for conflict in find_conflicts_in_changes(&device, &conf) {
+        eprintln!("Warning: {}", conflict);
+    }
+    wgif.set_config(conf).await
+}
+
+fn read_key_file(path: &Path) -> Result<Zeroizing<String>, WireCtlError> {
+    Ok(Zeroizing::new(std::fs::read_to_string(path)?))
+}
+
 async fn cmd_show(opt: &ShowCmd) -> Result<(), WireCtlError> {
+    if opt.json {
+        return show_json(opt).await;
+    }
+    if let Some(interval) = opt.watch {
+        return show_watch(opt, interval).await;
+    }
+
     if let Some(ifname) = &opt.interface {
         let wgif = WgInterface::get_interface(ifname).await?;
         show_interface(&wgif, opt, false).await?;
@@ -82,6 +166,38 @@ async fn cmd_show(opt: &ShowCmd) -> Result<(), WireCtlError> {
     Ok(())
 }
 
+async fn show_json(opt: &ShowCmd) -> Result<(), WireCtlError> {
+    let list = if let Some(ifname) = &opt.interface {
+        vec![WgInterface::get_interface(ifname).await?]
+    } else {
+        WgInterface::get_interfaces().await?
+    };
+
+    let mut devices = Vec::with_capacity(list.len());
+    for wgif in &list {
+        devices.push(wgif.get_config().await?);
+    }
+    json::print_devices(&devices, opt.interface.is_some())
+}
+
+async fn show_watch(opt: &ShowCmd, interval: Duration) -> Result<(), WireCtlError> {
+    let list = if let Some(ifname) = &opt.interface {
+        vec![WgInterface::get_interface(ifname).await?]
+    } else {
+        WgInterface::get_interfaces().await?
+    };
+
+    let mut watchers: Vec<_> = list
+        .into_iter()
+        .map(|wgif| StatsWatcher::new(wgif).set_interval(interval))
+        .collect();
+    loop {
+        for watcher in &mut watchers {
+            print_stats(&watcher.next_snapshot().await?);
+        }
+    }
+}
+
 async fn show_interface(
     wgif: &WgInterface,
     opt: &ShowCmd,
@@ -138,8 +254,8 @@ fn dump_interface(config: &WgDevice, fields: &ShowFields, print_ifname: bool) {
         if nff {
             print!("\t");
         }
-        if config.has_fwmark() {
-            print!("{}", config.fwmark);
+        if let Some(fwmark) = config.fwmark {
+            print!("{}", fwmark);
         } else {
             print!("off");
         }
@@ -158,15 +274,15 @@ fn dump_interface(config: &WgDevice, fields: &ShowFields, print_ifname: bool) {
             print!("{}", peer.public_key.to_base64());
 
             if fields.preshared_keys {
-                if peer.has_preshared_key() {
-                    print!("\t{}", peer.preshared_key.to_base64());
+                if let Some(preshared_key) = &peer.preshared_key {
+                    print!("\t{}", preshared_key.to_base64());
                 } else {
                     print!("\t(none)");
                 }
             }
             if fields.endpoints {
-                if peer.has_endpoint() {
-                    print!("\t{}", peer.endpoint);
+                if let Some(endpoint) = peer.endpoint {
+                    print!("\t{}", endpoint);
                 } else {
                     print!("\t(none)");
                 }
@@ -189,15 +305,18 @@ fn dump_interface(config: &WgDevice, fields: &ShowFields, print_ifname: bool) {
                 }
             }
             if fields.latest_handshakes {
-
-                print!("\t{}", peer.last_handshake.duration_since(UNIX_EPOCH).unwrap().as_secs());
+                let secs = peer
+                    .last_handshake
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                print!("\t{}", secs);
             }
             if fields.transfer {
                 print!("\t{}\t{}", peer.rx_bytes, peer.tx_bytes);
             }
             if fields.persistent_keepalive {
-                if peer.has_persistent_keepalive() {
-                    print!("\t{}", peer.persistent_keepalive);
+                if let Some(keepalive) = peer.persistent_keepalive {
+                    print!("\t{}", keepalive);
                 } else {
                     print!("\toff");
                 }
@@ -224,22 +343,22 @@ fn print_interface_pretty(config: &WgDevice) {
     if config.has_listen_port() {
         println!("  listening port: {}", config.listen_port);
     }
-    if config.has_fwmark() {
-        println!("  public key: {}", config.fwmark);
+    if let Some(fwmark) = config.fwmark {
+        println!("  fwmark: {}", fwmark);
     }
     println!();
 
     for peer in &config.peers {
         println!("peer: {}", peer.public_key.to_base64());
-        if peer.has_preshared_key() {
+        if let Some(preshared_key) = &peer.preshared_key {
             if let Ok("never") = env::var("WG_HIDE_KEYS").as_deref() {
-                println!("  preshared key: {}", peer.preshared_key.to_base64());
+                println!("  preshared key: {}", preshared_key.to_base64());
             } else {
                 println!("  preshared key: (hidden)");
             }
         }
-        if peer.has_endpoint() {
-            println!("  endpoint: {}", peer.endpoint);
+        if let Some(endpoint) = peer.endpoint {
+            println!("  endpoint: {}", endpoint);
         }
         print!("  allowed ips: ");
         if peer.allow_ips.is_empty() {
@@ -251,9 +370,9 @@ fn print_interface_pretty(config: &WgDevice) {
             println!();
         }
 
-        if peer.last_handshake != SystemTime::UNIX_EPOCH {
+        if let Some(last_handshake) = peer.last_handshake {
             print!("  latest handshake: ");
-            let time = OffsetDateTime::from(peer.last_handshake);
+            let time = OffsetDateTime::from(last_handshake);
             let dura = OffsetDateTime::now_utc() - time;
             if dura.is_positive() {
                 if dura.whole_days() > 0 {
@@ -285,17 +404,36 @@ fn print_interface_pretty(config: &WgDevice) {
             format_bytes(peer.rx_bytes),
             format_bytes(peer.tx_bytes)
         );
-        if peer.has_persistent_keepalive() {
-            print!(
-                "  persistent keepalive: every {} seconds",
-                peer.persistent_keepalive
-            );
+        if let Some(keepalive) = peer.persistent_keepalive {
+            print!("  persistent keepalive: every {} seconds", keepalive);
         }
 
         println!();
     }
 }
 
+fn print_stats(snapshot: &StatsSnapshot) {
+    println!("interface: {}", snapshot.device_name);
+    for peer in &snapshot.peers {
+        println!("  peer: {}", peer.public_key.to_base64());
+        println!(
+            "    transfer: {}/s received, {}/s sent",
+            format_bytes(peer.rx_rate as u64),
+            format_bytes(peer.tx_rate as u64)
+        );
+        if let Some(age) = peer.handshake_age {
+            println!(
+                "    latest handshake: {} ago",
+                format_pluralize(age.as_secs() as i64, "second", "seconds")
+            );
+        }
+        if peer.counter_reset {
+            println!("    counters reset");
+        }
+    }
+    println!();
+}
+
 fn format_pluralize(cnt: i64, unit: &str, plural: &str) -> String {
     if cnt > 1 {
         format!("{} {}", cnt, plural)
@@ -0,0 +1,148 @@
+use super::{error_code, link_index, EEXIST};
+use crate::{
+    netns::NetNs,
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    WireCtlError,
+};
+use futures::TryStreamExt;
+use ipnetwork::IpNetwork;
+use rtnetlink::{
+    packet::{nlas::address::Nla, AddressMessage, AF_INET, AF_INET6},
+    Handle,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The address of an address message, with its prefix length
+pub(super) fn address_of(msg: &AddressMessage) -> Option<IpNetwork> {
+    let mut address = None;
+    let mut local = None;
+    for nla in &msg.nlas {
+        match nla {
+            Nla::Address(bytes) => address = Some(bytes),
+            Nla::Local(bytes) => local = Some(bytes),
+            _ => (),
+        }
+    }
+    // On point-to-point links, IFA_ADDRESS is the address of the other end
+    let bytes = local.or(address)?;
+
+    let ip: IpAddr = match msg.header.family as u16 {
+        AF_INET => Ipv4Addr::from(<[u8; 4]>::try_from(bytes.as_slice()).ok()?).into(),
+        AF_INET6 => Ipv6Addr::from(<[u8; 16]>::try_from(bytes.as_slice()).ok()?).into(),
+        _ => return None,
+    };
+    IpNetwork::new(ip, msg.header.prefix_len).ok()
+}
+
+pub(super) async fn address_messages(
+    handle: &Handle,
+    index: u32,
+    timeouts: &Timeouts,
+) -> Result<Vec<AddressMessage>, WireCtlError> {
+    let messages = handle
+        .address()
+        .get()
+        .set_link_index_filter(index)
+        .execute();
+    with_timeout(timeouts, TimeoutPhase::Read, async {
+        Ok(messages.try_collect().await?)
+    })
+    .await
+}
+
+async fn add(
+    handle: &Handle,
+    index: u32,
+    address: IpNetwork,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let request = handle
+        .address()
+        .add(index, address.ip(), address.prefix())
+        .execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async {
+        match request.await {
+            Ok(()) => Ok(()),
+            Err(e) if error_code(&e) == Some(EEXIST) => {
+                Err(WireCtlError::AddressAlreadyExists(address))
+            }
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await
+}
+
+async fn del(
+    handle: &Handle,
+    msg: AddressMessage,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let request = handle.address().del(msg).execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+pub(crate) async fn get_addresses(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<Vec<IpNetwork>, WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    let messages = address_messages(&handle, index, timeouts).await?;
+    Ok(messages.iter().filter_map(address_of).collect())
+}
+
+pub(crate) async fn add_address(
+    ifname: &str,
+    address: IpNetwork,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    add(&handle, index, address, timeouts).await
+}
+
+pub(crate) async fn remove_address(
+    ifname: &str,
+    address: &IpNetwork,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    let msg = address_messages(&handle, index, timeouts)
+        .await?
+        .into_iter()
+        .find(|msg| address_of(msg).as_ref() == Some(address))
+        .ok_or(WireCtlError::AddressNotFound(*address))?;
+    del(&handle, msg, timeouts).await
+}
+
+pub(crate) async fn set_addresses(
+    ifname: &str,
+    addresses: &[IpNetwork],
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    let messages = address_messages(&handle, index, timeouts).await?;
+
+    let mut current = Vec::with_capacity(messages.len());
+    for msg in messages {
+        match address_of(&msg) {
+            Some(address) if addresses.contains(&address) => current.push(address),
+            // Addresses without a known family are left alone
+            Some(_) => del(&handle, msg, timeouts).await?,
+            None => (),
+        }
+    }
+    for address in addresses {
+        if !current.contains(address) {
+            add(&handle, index, *address, timeouts).await?;
+            current.push(*address);
+        }
+    }
+    Ok(())
+}
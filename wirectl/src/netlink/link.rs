@@ -0,0 +1,198 @@
+use super::{link_index, link_message};
+use crate::{
+    netns::NetNs,
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    WireCtlError,
+};
+use rtnetlink::{
+    packet::{
+        nlas::link::{Nla, State, Stats64, Stats64Buffer},
+        traits::Parseable,
+        IFF_LOWER_UP, IFF_RUNNING, IFF_UP,
+    },
+    Handle, LinkSetRequest,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::os::unix::io::AsRawFd;
+
+/// Operational state of a link, as defined by RFC 2863
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OperState {
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+
+impl From<State> for OperState {
+    fn from(state: State) -> Self {
+        match state {
+            State::NotPresent => OperState::NotPresent,
+            State::Down => OperState::Down,
+            State::LowerLayerDown => OperState::LowerLayerDown,
+            State::Testing => OperState::Testing,
+            State::Dormant => OperState::Dormant,
+            State::Up => OperState::Up,
+            State::Unknown | State::Other(_) => OperState::Unknown,
+        }
+    }
+}
+
+/// Kernel level state of a link
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LinkInfo {
+    pub index: u32,
+    /// `IFF_*` flags of the link
+    pub flags: u32,
+    pub mtu: u32,
+    /// Tunnel links usually report [`OperState::Unknown`] while they are up
+    pub operstate: OperState,
+}
+
+impl LinkInfo {
+    /// Whether the link was brought up administratively
+    pub fn is_up(&self) -> bool {
+        self.flags & IFF_UP != 0
+    }
+
+    /// Whether the link is up and able to carry packets
+    pub fn is_running(&self) -> bool {
+        self.flags & IFF_RUNNING != 0 && self.flags & IFF_LOWER_UP != 0
+    }
+}
+
+/// Kernel counters of a link
+///
+/// Unlike the counters of [`crate::types::Peer`], they include the packets
+/// the kernel dropped before reaching Wireguard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LinkStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+impl From<Stats64> for LinkStats {
+    fn from(stats: Stats64) -> Self {
+        Self {
+            rx_packets: stats.rx_packets,
+            tx_packets: stats.tx_packets,
+            rx_bytes: stats.rx_bytes,
+            tx_bytes: stats.tx_bytes,
+            rx_errors: stats.rx_errors,
+            tx_errors: stats.tx_errors,
+            rx_dropped: stats.rx_dropped,
+            tx_dropped: stats.tx_dropped,
+        }
+    }
+}
+
+async fn execute_set(
+    handle: &Handle,
+    ifname: &str,
+    timeouts: &Timeouts,
+    build: impl FnOnce(LinkSetRequest) -> LinkSetRequest,
+) -> Result<(), WireCtlError> {
+    let index = link_index(handle, ifname, timeouts).await?;
+    let request = build(handle.link().set(index)).execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+pub(crate) async fn set_link_up(
+    ifname: &str,
+    up: bool,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    execute_set(&handle, ifname, timeouts, |request| {
+        if up {
+            request.up()
+        } else {
+            request.down()
+        }
+    })
+    .await
+}
+
+pub(crate) async fn set_mtu(
+    ifname: &str,
+    mtu: u32,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    execute_set(&handle, ifname, timeouts, |request| request.mtu(mtu)).await
+}
+
+/// Move the link from `netns` to `target`
+///
+/// The link is down in `target`, without its addresses and routes.
+pub(crate) async fn move_to_netns(
+    ifname: &str,
+    target: &NetNs,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let target = target.open()?;
+    let handle = super::connect(netns)?;
+    execute_set(&handle, ifname, timeouts, |request| {
+        request.setns_by_fd(target.as_raw_fd())
+    })
+    .await
+}
+
+pub(crate) async fn get_link_info(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<LinkInfo, WireCtlError> {
+    let handle = super::connect(netns)?;
+    let link = link_message(&handle, ifname, timeouts).await?;
+
+    let mut info = LinkInfo {
+        index: link.header.index,
+        flags: link.header.flags,
+        mtu: 0,
+        operstate: OperState::Unknown,
+    };
+    for nla in link.nlas {
+        match nla {
+            Nla::Mtu(mtu) => info.mtu = mtu,
+            Nla::OperState(state) => info.operstate = state.into(),
+            _ => (),
+        }
+    }
+    Ok(info)
+}
+
+pub(crate) async fn get_link_stats(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<LinkStats, WireCtlError> {
+    let handle = super::connect(netns)?;
+    let link = link_message(&handle, ifname, timeouts).await?;
+
+    let stats = link.nlas.iter().find_map(|nla| match nla {
+        Nla::Stats64(bytes) => Stats64Buffer::new_checked(bytes.as_slice())
+            .and_then(|buffer| Stats64::parse(&buffer))
+            .ok(),
+        _ => None,
+    });
+    stats
+        .map(LinkStats::from)
+        .ok_or(WireCtlError::InvalidProtocol)
+}
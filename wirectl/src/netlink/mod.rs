@@ -0,0 +1,74 @@
+//! Link level configuration through rtnetlink
+//!
+//! Like the userspace IPC, every operation opens its own connection. Queries are
+//! bounded by the read deadline, and modifications by the write deadline.
+use crate::{
+    netns::{self, NetNs},
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    WireCtlError,
+};
+use futures::TryStreamExt;
+use rtnetlink::{
+    new_connection_with_socket, packet::LinkMessage, sys::SmolSocket, Error as NlError, Handle,
+};
+
+mod address;
+mod link;
+mod route;
+mod rule;
+mod shaping;
+
+pub(crate) use address::*;
+pub use link::*;
+pub use route::*;
+pub(crate) use rule::*;
+pub(crate) use shaping::*;
+
+const ENODEV: i32 = 19;
+const EEXIST: i32 = 17;
+
+/// Open a new rtnetlink connection, to `netns` if given
+pub(crate) fn connect(netns: Option<&NetNs>) -> Result<Handle, WireCtlError> {
+    let (connection, handle, _) = netns::run_in(netns, new_connection_with_socket::<SmolSocket>)??;
+    smol::spawn(connection).detach();
+    Ok(handle)
+}
+
+/// The link named `ifname`
+pub(crate) async fn link_message(
+    handle: &Handle,
+    ifname: &str,
+    timeouts: &Timeouts,
+) -> Result<LinkMessage, WireCtlError> {
+    let mut links = handle.link().get().match_name(ifname.to_owned()).execute();
+    let link = with_timeout(timeouts, TimeoutPhase::Read, async {
+        match links.try_next().await {
+            Ok(link) => Ok(link),
+            Err(e) if error_code(&e) == Some(ENODEV) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await?;
+
+    link.ok_or(WireCtlError::NotFound)
+}
+
+/// The index of the link named `ifname`
+pub(crate) async fn link_index(
+    handle: &Handle,
+    ifname: &str,
+    timeouts: &Timeouts,
+) -> Result<u32, WireCtlError> {
+    Ok(link_message(handle, ifname, timeouts).await?.header.index)
+}
+
+/// The errno of a netlink error
+pub(crate) fn error_code(e: &NlError) -> Option<i32> {
+    match e {
+        NlError::NetlinkError(msg) => Some(-msg.code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
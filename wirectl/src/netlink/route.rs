@@ -0,0 +1,294 @@
+use super::{
+    address::{address_messages, address_of},
+    link_index,
+};
+use crate::{
+    allowedips::normalize,
+    netns::NetNs,
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    types::*,
+    WireCtlError,
+};
+use futures::TryStreamExt;
+use ipnetwork::IpNetwork;
+use rtnetlink::{
+    packet::{
+        nlas::route::Nla, RouteMessage, AF_INET, AF_INET6, RT_SCOPE_LINK, RT_TABLE_MAIN,
+        RT_TABLE_UNSPEC,
+    },
+    Handle, IpVersion,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Routing protocol of the routes added by wirectl, unassigned by iproute2
+///
+/// Only the routes carrying it are removed, which leaves the routes of other
+/// tools on the link alone. They are listed by `ip route show proto 87`.
+pub const RTPROT_WIRECTL: u8 = 87;
+
+/// Where the routes of [`crate::interface::WgInterface::sync_routes()`] go
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RouteOptions {
+    /// Routing table, the main table if unset
+    pub table: Option<u32>,
+    /// Metric of the routes, the kernel default if unset
+    pub metric: Option<u32>,
+}
+
+impl RouteOptions {
+    pub fn set_table(mut self, table: u32) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    pub fn set_metric(mut self, metric: u32) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    fn table(&self) -> u32 {
+        self.table.unwrap_or(RT_TABLE_MAIN as u32)
+    }
+}
+
+/// The routes added and removed by [`crate::interface::WgInterface::sync_routes()`]
+///
+/// A route whose metric changed is both removed and added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RouteChanges {
+    pub added: Vec<IpNetwork>,
+    pub removed: Vec<IpNetwork>,
+    /// Allowed IPs left alone because another tool or link already routes
+    /// them in the table
+    pub skipped: Vec<IpNetwork>,
+}
+
+impl RouteChanges {
+    /// Whether no route was added or removed, whatever was skipped
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The destination of a route message, with its prefix length
+fn destination_of(msg: &RouteMessage) -> Option<IpNetwork> {
+    let bytes = msg.nlas.iter().find_map(|nla| match nla {
+        Nla::Destination(bytes) => Some(bytes.as_slice()),
+        _ => None,
+    });
+
+    // Default routes have no destination attribute
+    let ip: IpAddr = match (msg.header.address_family as u16, bytes) {
+        (AF_INET, None) => Ipv4Addr::UNSPECIFIED.into(),
+        (AF_INET6, None) => Ipv6Addr::UNSPECIFIED.into(),
+        (AF_INET, Some(bytes)) => Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).into(),
+        (AF_INET6, Some(bytes)) => Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).into(),
+        _ => return None,
+    };
+    IpNetwork::new(ip, msg.header.destination_prefix_length).ok()
+}
+
+pub(super) fn table_of(msg: &RouteMessage) -> u32 {
+    msg.nlas
+        .iter()
+        .find_map(|nla| match nla {
+            Nla::Table(table) => Some(*table),
+            _ => None,
+        })
+        .unwrap_or(msg.header.table as u32)
+}
+
+fn metric_of(msg: &RouteMessage) -> Option<u32> {
+    msg.nlas.iter().find_map(|nla| match nla {
+        Nla::Priority(metric) => Some(*metric),
+        _ => None,
+    })
+}
+
+fn output_interface_of(msg: &RouteMessage) -> Option<u32> {
+    msg.nlas.iter().find_map(|nla| match nla {
+        Nla::Oif(index) => Some(*index),
+        _ => None,
+    })
+}
+
+pub(super) async fn route_messages(
+    handle: &Handle,
+    version: IpVersion,
+    timeouts: &Timeouts,
+) -> Result<Vec<RouteMessage>, WireCtlError> {
+    let messages = handle.route().get(version).execute();
+    with_timeout(timeouts, TimeoutPhase::Read, async {
+        Ok(messages.try_collect().await?)
+    })
+    .await
+}
+
+/// The routes of the table of `options`
+async fn table_routes(
+    handle: &Handle,
+    options: &RouteOptions,
+    timeouts: &Timeouts,
+) -> Result<Vec<(IpNetwork, RouteMessage)>, WireCtlError> {
+    let mut routes = Vec::new();
+    for version in [IpVersion::V4, IpVersion::V6] {
+        routes.extend(
+            route_messages(handle, version, timeouts)
+                .await?
+                .into_iter()
+                .filter(|msg| table_of(msg) == options.table())
+                .filter_map(|msg| Some((destination_of(&msg)?, msg))),
+        );
+    }
+    Ok(routes)
+}
+
+/// Whether the route was added by wirectl through the link `index`
+fn is_owned(msg: &RouteMessage, index: u32) -> bool {
+    msg.header.protocol == RTPROT_WIRECTL && output_interface_of(msg) == Some(index)
+}
+
+/// The routes added by wirectl through the link `index` in the table of `options`
+pub(super) async fn owned_routes(
+    handle: &Handle,
+    index: u32,
+    options: &RouteOptions,
+    timeouts: &Timeouts,
+) -> Result<Vec<(IpNetwork, RouteMessage)>, WireCtlError> {
+    let mut routes = table_routes(handle, options, timeouts).await?;
+    routes.retain(|(_, msg)| is_owned(msg, index));
+    Ok(routes)
+}
+
+pub(super) async fn add(
+    handle: &Handle,
+    index: u32,
+    destination: IpNetwork,
+    options: &RouteOptions,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut request = handle
+        .route()
+        .add()
+        .output_interface(index)
+        .protocol(RTPROT_WIRECTL)
+        .scope(RT_SCOPE_LINK);
+
+    let msg = request.message_mut();
+    let table = options.table();
+    // Tables above 255 only fit the attribute
+    msg.header.table = u8::try_from(table).unwrap_or(RT_TABLE_UNSPEC);
+    msg.nlas.push(Nla::Table(table));
+    if let Some(metric) = options.metric {
+        msg.nlas.push(Nla::Priority(metric));
+    }
+
+    with_timeout(timeouts, TimeoutPhase::Write, async {
+        match destination {
+            IpNetwork::V4(net) => {
+                request
+                    .v4()
+                    .destination_prefix(net.network(), net.prefix())
+                    .execute()
+                    .await?
+            }
+            IpNetwork::V6(net) => {
+                request
+                    .v6()
+                    .destination_prefix(net.network(), net.prefix())
+                    .execute()
+                    .await?
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+pub(super) async fn del(
+    handle: &Handle,
+    msg: RouteMessage,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let request = handle.route().del(msg).execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+/// Whether `network` is inside the prefix of one of `addresses`
+fn is_connected(network: &IpNetwork, addresses: &[IpNetwork]) -> bool {
+    addresses.iter().any(|address| {
+        address.is_ipv4() == network.is_ipv4()
+            && address.prefix() <= network.prefix()
+            && address.contains(network.network())
+    })
+}
+
+pub(crate) async fn sync_routes(
+    ifname: &str,
+    device: &WgDevice,
+    options: &RouteOptions,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<RouteChanges, WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+
+    // The kernel already routes the prefixes of the addresses of the link
+    let addresses: Vec<IpNetwork> = address_messages(&handle, index, timeouts)
+        .await?
+        .iter()
+        .filter_map(address_of)
+        .collect();
+
+    // Default routes would take the encrypted packets too, see `FullTunnel`
+    let mut wanted: Vec<IpNetwork> = Vec::new();
+    for peer in &device.peers {
+        for allowed_ip in peer.allow_ips.iter().map(normalize) {
+            if allowed_ip.prefix() != 0
+                && !wanted.contains(&allowed_ip)
+                && !is_connected(&allowed_ip, &addresses)
+            {
+                wanted.push(allowed_ip);
+            }
+        }
+    }
+
+    let (owned, foreign): (Vec<_>, Vec<_>) = table_routes(&handle, options, timeouts)
+        .await?
+        .into_iter()
+        .partition(|(_, msg)| is_owned(msg, index));
+
+    // Adding a route the table already has fails, so the destinations routed
+    // by other tools or links are skipped before anything changes
+    let mut changes = RouteChanges::default();
+    wanted.retain(|allowed_ip| {
+        let taken = foreign
+            .iter()
+            .any(|(destination, _)| destination == allowed_ip);
+        if taken {
+            changes.skipped.push(*allowed_ip);
+        }
+        !taken
+    });
+
+    let mut current = Vec::new();
+    for (destination, msg) in owned {
+        let stale = options.metric.is_some() && metric_of(&msg) != options.metric;
+        if wanted.contains(&destination) && !stale && !current.contains(&destination) {
+            current.push(destination);
+        } else {
+            del(&handle, msg, timeouts).await?;
+            changes.removed.push(destination);
+        }
+    }
+    for destination in wanted {
+        if !current.contains(&destination) {
+            add(&handle, index, destination, options, timeouts).await?;
+            changes.added.push(destination);
+        }
+    }
+    Ok(changes)
+}
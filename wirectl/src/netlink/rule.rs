@@ -0,0 +1,230 @@
+use super::{
+    link_index,
+    route::{self, RouteOptions},
+};
+use crate::{
+    netns::NetNs,
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    WireCtlError,
+};
+use futures::TryStreamExt;
+use ipnetwork::IpNetwork;
+use rtnetlink::{
+    packet::{
+        nlas::rule::Nla, RuleMessage, AF_INET, AF_INET6, FIB_RULE_INVERT, FR_ACT_TO_TBL,
+        RT_TABLE_COMPAT, RT_TABLE_DEFAULT, RT_TABLE_LOCAL, RT_TABLE_MAIN, RT_TABLE_UNSPEC,
+    },
+    Handle, IpVersion,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+fn table_of(msg: &RuleMessage) -> u32 {
+    msg.nlas
+        .iter()
+        .find_map(|nla| match nla {
+            Nla::Table(table) => Some(*table),
+            _ => None,
+        })
+        .unwrap_or(msg.header.table as u32)
+}
+
+fn fwmark_of(msg: &RuleMessage) -> Option<u32> {
+    msg.nlas.iter().find_map(|nla| match nla {
+        Nla::FwMark(fwmark) => Some(*fwmark),
+        _ => None,
+    })
+}
+
+/// `not fwmark <fwmark> table <table>`
+fn is_tunnel_rule(msg: &RuleMessage, table: u32, fwmark: u32) -> bool {
+    msg.header.flags & FIB_RULE_INVERT != 0
+        && fwmark_of(msg) == Some(fwmark)
+        && table_of(msg) == table
+}
+
+/// `table main suppress_prefixlength 0`
+fn is_suppress_rule(msg: &RuleMessage) -> bool {
+    table_of(msg) == RT_TABLE_MAIN as u32
+        && msg
+            .nlas
+            .iter()
+            .any(|nla| matches!(nla, Nla::SuppressPrefixLen(0)))
+}
+
+fn version(ipv6: bool) -> IpVersion {
+    if ipv6 {
+        IpVersion::V6
+    } else {
+        IpVersion::V4
+    }
+}
+
+fn default_route(ipv6: bool) -> IpNetwork {
+    // The unspecified address always fits a zero prefix
+    if ipv6 {
+        IpNetwork::new(Ipv6Addr::UNSPECIFIED.into(), 0).unwrap()
+    } else {
+        IpNetwork::new(Ipv4Addr::UNSPECIFIED.into(), 0).unwrap()
+    }
+}
+
+async fn rule_messages(
+    handle: &Handle,
+    ipv6: bool,
+    timeouts: &Timeouts,
+) -> Result<Vec<RuleMessage>, WireCtlError> {
+    let messages = handle.rule().get(version(ipv6)).execute();
+    with_timeout(timeouts, TimeoutPhase::Read, async {
+        Ok(messages.try_collect().await?)
+    })
+    .await
+}
+
+/// Add a rule sending to `table`
+async fn add(
+    handle: &Handle,
+    ipv6: bool,
+    table: u32,
+    nlas: Vec<Nla>,
+    flags: u32,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut request = handle.rule().add();
+    let msg = request.message_mut();
+    msg.header.family = if ipv6 { AF_INET6 } else { AF_INET } as u8;
+    msg.header.action = FR_ACT_TO_TBL;
+    msg.header.flags = flags;
+    // Tables above 255 only fit the attribute
+    msg.header.table = u8::try_from(table).unwrap_or(RT_TABLE_UNSPEC);
+    msg.nlas.push(Nla::Table(table));
+    msg.nlas.extend(nlas);
+
+    let request = request.execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+async fn del(handle: &Handle, msg: RuleMessage, timeouts: &Timeouts) -> Result<(), WireCtlError> {
+    let request = handle.rule().del(msg).execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+/// The first number from `start` which is neither a reserved table, a routing
+/// table with routes, nor used by a rule as a table or a firewall mark
+pub(crate) async fn free_table(
+    start: u32,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<u32, WireCtlError> {
+    let handle = super::connect(netns)?;
+
+    let mut used: Vec<u32> = [
+        RT_TABLE_UNSPEC,
+        RT_TABLE_COMPAT,
+        RT_TABLE_DEFAULT,
+        RT_TABLE_MAIN,
+        RT_TABLE_LOCAL,
+    ]
+    .into_iter()
+    .map(u32::from)
+    .collect();
+    for ipv6 in [false, true] {
+        for msg in route::route_messages(&handle, version(ipv6), timeouts).await? {
+            used.push(route::table_of(&msg));
+        }
+        for msg in rule_messages(&handle, ipv6, timeouts).await? {
+            used.push(table_of(&msg));
+            used.extend(fwmark_of(&msg));
+        }
+    }
+
+    (start..=u32::MAX)
+        .find(|table| !used.contains(table))
+        .ok_or(WireCtlError::NotFound)
+}
+
+/// Route everything through `ifname`, except the packets marked with `fwmark`
+///
+/// The default route goes into `table`, which is looked up for the packets
+/// without `fwmark`. The main table still takes precedence for its non default
+/// routes.
+pub(crate) async fn add_full_tunnel(
+    ifname: &str,
+    table: u32,
+    fwmark: u32,
+    ipv6: bool,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+
+    let options = RouteOptions::default().set_table(table);
+    let default = default_route(ipv6);
+    let routes = route::owned_routes(&handle, index, &options, timeouts).await?;
+    if !routes
+        .iter()
+        .any(|(destination, _)| destination == &default)
+    {
+        route::add(&handle, index, default, &options, timeouts).await?;
+    }
+
+    // The kernel gives each new rule its own priority, so that adding the same
+    // rule twice would duplicate it
+    let rules = rule_messages(&handle, ipv6, timeouts).await?;
+    if !rules.iter().any(|msg| is_tunnel_rule(msg, table, fwmark)) {
+        let nlas = vec![Nla::FwMark(fwmark)];
+        add(&handle, ipv6, table, nlas, FIB_RULE_INVERT, timeouts).await?;
+    }
+    if !rules.iter().any(is_suppress_rule) {
+        let nlas = vec![Nla::SuppressPrefixLen(0)];
+        add(&handle, ipv6, RT_TABLE_MAIN as u32, nlas, 0, timeouts).await?;
+    }
+    Ok(())
+}
+
+/// Undo [`add_full_tunnel()`]
+///
+/// The rule suppressing the default routes of the main table is shared, it is
+/// only removed when no other full tunnel uses it.
+pub(crate) async fn del_full_tunnel(
+    ifname: &str,
+    table: u32,
+    fwmark: u32,
+    ipv6: bool,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+
+    let mut others = false;
+    let mut suppress = Vec::new();
+    for msg in rule_messages(&handle, ipv6, timeouts).await? {
+        if is_tunnel_rule(&msg, table, fwmark) {
+            del(&handle, msg, timeouts).await?;
+        } else if is_suppress_rule(&msg) {
+            suppress.push(msg);
+        } else if msg.header.flags & FIB_RULE_INVERT != 0 && fwmark_of(&msg).is_some() {
+            others = true;
+        }
+    }
+    if !others {
+        for msg in suppress {
+            del(&handle, msg, timeouts).await?;
+        }
+    }
+
+    // The routes of the table are gone with the link
+    let index = match link_index(&handle, ifname, timeouts).await {
+        Ok(index) => index,
+        Err(WireCtlError::NotFound) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let options = RouteOptions::default().set_table(table);
+    let default = default_route(ipv6);
+    for (destination, msg) in route::owned_routes(&handle, index, &options, timeouts).await? {
+        if destination == default {
+            route::del(&handle, msg, timeouts).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,560 @@
+use super::link_index;
+use crate::{
+    netns::NetNs,
+    shaping::{PeerShaping, Shaping},
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    types::WgDevice,
+    WireCtlError,
+};
+use futures::{StreamExt, TryStreamExt};
+use ipnetwork::IpNetwork;
+use rtnetlink::{
+    packet::{
+        nlas::{DefaultNla, Nla as _},
+        tc::{self, constants::TC_U32_TERMINAL, u32 as cls_u32, TcOpt},
+        NetlinkMessage, NetlinkPayload, RtnlMessage, TcMessage, NLM_F_ACK, NLM_F_CREATE,
+        NLM_F_REPLACE, NLM_F_REQUEST,
+    },
+    Error as NlError, Handle,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const ENOENT: i32 = 2;
+
+const TC_H_ROOT: u32 = 0xffff_ffff;
+/// Handle of the HTB qdisc, `1:`
+const ROOT_HANDLE: u32 = 1 << 16;
+
+const TCA_HTB_PARMS: u16 = 1;
+const TCA_HTB_INIT: u16 = 2;
+const TCA_HTB_RATE64: u16 = 6;
+const TCA_HTB_CEIL64: u16 = 7;
+
+const TC_HTB_PROTOVER: u32 = 3;
+const TC_LINKLAYER_ETHERNET: u8 = 1;
+/// Tries to fill the quantum of a class every 1/10th of its rate
+const HTB_RATE2QUANTUM: u32 = 10;
+
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+
+/// The filters of each family have their own priority, alternating between
+/// two pairs so that the filters of an update can be added next to the
+/// previous ones
+const PRIORITIES: [(u16, u16); 2] = [(1, 2), (3, 4)];
+
+/// Bytes a class may send at once, as `tc` computes it for `HZ=1000`
+fn burst(rate: u64) -> u64 {
+    rate / 1000 + 1600
+}
+
+/// The time to send `size` bytes at `rate`, in scheduler ticks of 64ns
+fn xmit_ticks(rate: u64, size: u64) -> u32 {
+    let nanos = size as u128 * 1_000_000_000 / rate as u128;
+    (nanos >> 6).min(u32::MAX as u128) as u32
+}
+
+/// `struct tc_ratespec`, with `rate` in bytes per second
+fn ratespec(rate: u64) -> [u8; 12] {
+    let mut spec = [0; 12];
+    spec[1] = TC_LINKLAYER_ETHERNET;
+    spec[8..12].copy_from_slice(&(rate.min(u32::MAX as u64) as u32).to_ne_bytes());
+    spec
+}
+
+/// The options of an HTB class limited to `rate` and `ceil` bits per second
+pub(super) fn htb_class_options(rate: u64, ceil: u64) -> Vec<TcOpt> {
+    let (rate, ceil) = (rate / 8, ceil / 8);
+    let quantum = (rate / HTB_RATE2QUANTUM as u64).clamp(1000, 200_000) as u32;
+
+    // struct tc_htb_opt
+    let mut parms = Vec::with_capacity(44);
+    parms.extend_from_slice(&ratespec(rate));
+    parms.extend_from_slice(&ratespec(ceil));
+    parms.extend_from_slice(&xmit_ticks(rate, burst(rate)).to_ne_bytes());
+    parms.extend_from_slice(&xmit_ticks(ceil, burst(ceil)).to_ne_bytes());
+    parms.extend_from_slice(&quantum.to_ne_bytes());
+    // Level and priority
+    parms.extend_from_slice(&[0; 8]);
+
+    let mut options = vec![TcOpt::Other(DefaultNla::new(TCA_HTB_PARMS, parms))];
+    // Rates which don't fit in the ratespec are given separately
+    if rate > u32::MAX as u64 {
+        options.push(TcOpt::Other(DefaultNla::new(
+            TCA_HTB_RATE64,
+            rate.to_ne_bytes().to_vec(),
+        )));
+    }
+    if ceil > u32::MAX as u64 {
+        options.push(TcOpt::Other(DefaultNla::new(
+            TCA_HTB_CEIL64,
+            ceil.to_ne_bytes().to_vec(),
+        )));
+    }
+    options
+}
+
+/// The rate and ceil of an HTB class, in bits per second
+pub(super) fn htb_class_rates(msg: &TcMessage) -> Option<(u64, u64)> {
+    let options = msg.nlas.iter().find_map(|nla| match nla {
+        tc::Nla::Options(options) => Some(options),
+        _ => None,
+    })?;
+    let (mut rate, mut ceil) = (None, None);
+    for option in options {
+        let TcOpt::Other(nla) = option else { continue };
+        let mut value = vec![0; nla.value_len()];
+        nla.emit_value(&mut value);
+        match nla.kind() {
+            TCA_HTB_PARMS if value.len() >= 24 => {
+                rate = rate.or(Some(
+                    u32::from_ne_bytes(value[8..12].try_into().unwrap()) as u64
+                ));
+                ceil = ceil.or(Some(
+                    u32::from_ne_bytes(value[20..24].try_into().unwrap()) as u64
+                ));
+            }
+            TCA_HTB_RATE64 if value.len() == 8 => {
+                rate = Some(u64::from_ne_bytes(value.try_into().unwrap()))
+            }
+            TCA_HTB_CEIL64 if value.len() == 8 => {
+                ceil = Some(u64::from_ne_bytes(value.try_into().unwrap()))
+            }
+            _ => (),
+        }
+    }
+    Some((rate? * 8, ceil? * 8))
+}
+
+/// A u32 selector matching the destination addresses of `network`
+///
+/// Keys hold the bytes of the packet as they are in memory.
+pub(super) fn destination_selector(network: &IpNetwork) -> cls_u32::Sel {
+    let (offset, address, mask) = match network {
+        IpNetwork::V4(net) => (
+            16,
+            net.network().octets().to_vec(),
+            net.mask().octets().to_vec(),
+        ),
+        IpNetwork::V6(net) => (
+            24,
+            net.network().octets().to_vec(),
+            net.mask().octets().to_vec(),
+        ),
+    };
+    let mut keys: Vec<_> = address
+        .chunks(4)
+        .zip(mask.chunks(4))
+        .zip((offset..).step_by(4))
+        .filter(|((_, mask), _)| mask.iter().any(|&byte| byte != 0))
+        .map(|((address, mask), off)| cls_u32::Key {
+            mask: u32::from_ne_bytes(mask.try_into().unwrap()),
+            val: u32::from_ne_bytes(address.try_into().unwrap()),
+            off,
+            offmask: 0,
+        })
+        .collect();
+    if keys.is_empty() {
+        // Matches every packet
+        keys.push(cls_u32::Key {
+            off: offset,
+            ..Default::default()
+        });
+    }
+    cls_u32::Sel {
+        flags: TC_U32_TERMINAL,
+        nkeys: keys.len() as u8,
+        keys,
+        ..Default::default()
+    }
+}
+
+/// The destination network matched by a selector of
+/// [`destination_selector()`]
+pub(super) fn selector_network(sel: &cls_u32::Sel, ipv6: bool) -> Option<IpNetwork> {
+    let offset = if ipv6 { 24 } else { 16 };
+    let len = if ipv6 { 16 } else { 4 };
+    let mut address = vec![0; len];
+    let mut prefix = 0;
+    for key in &sel.keys {
+        let start = usize::try_from(key.off - offset).ok()?;
+        address
+            .get_mut(start..start + 4)?
+            .copy_from_slice(&(key.val & key.mask).to_ne_bytes());
+        prefix += key.mask.count_ones();
+    }
+    let address = if ipv6 {
+        IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(address).unwrap()))
+    } else {
+        IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(address).unwrap()))
+    };
+    IpNetwork::new(address, prefix as u8).ok()
+}
+
+/// Send a traffic control message, waiting for its acknowledgement
+async fn request(
+    handle: &Handle,
+    message: RtnlMessage,
+    flags: u16,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut req = NetlinkMessage::from(message);
+    req.header.flags = NLM_F_REQUEST | NLM_F_ACK | flags;
+    let mut handle = handle.clone();
+    with_timeout(timeouts, TimeoutPhase::Write, async {
+        let mut response = handle.request(req)?;
+        while let Some(msg) = response.next().await {
+            if let NetlinkPayload::Error(e) = msg.payload {
+                return Err(NlError::NetlinkError(e).into());
+            }
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Delete the root qdisc of the link, restoring the default one
+async fn del_root_qdisc(
+    handle: &Handle,
+    index: u32,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut msg = TcMessage::with_index(index as i32);
+    msg.header.parent = TC_H_ROOT;
+    match request(handle, RtnlMessage::DelQueueDiscipline(msg), 0, timeouts).await {
+        Err(WireCtlError::Io(e)) if e.raw_os_error() == Some(ENOENT) => Ok(()),
+        result => result,
+    }
+}
+
+/// Create the qdisc, or change it if it already has the same kind
+async fn replace_qdisc(
+    handle: &Handle,
+    index: u32,
+    parent: u32,
+    qdisc_handle: u32,
+    kind: &str,
+    options: Vec<TcOpt>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut msg = TcMessage::with_index(index as i32);
+    msg.header.parent = parent;
+    msg.header.handle = qdisc_handle;
+    msg.nlas.push(tc::Nla::Kind(kind.to_owned()));
+    if !options.is_empty() {
+        msg.nlas.push(tc::Nla::Options(options));
+    }
+    let flags = NLM_F_CREATE | NLM_F_REPLACE;
+    request(
+        handle,
+        RtnlMessage::NewQueueDiscipline(msg),
+        flags,
+        timeouts,
+    )
+    .await
+}
+
+/// Create the class, or change its rates
+async fn replace_class(
+    handle: &Handle,
+    index: u32,
+    classid: u32,
+    peer: &PeerShaping,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut msg = TcMessage::with_index(index as i32);
+    msg.header.parent = ROOT_HANDLE;
+    msg.header.handle = classid;
+    msg.nlas.push(tc::Nla::Kind("htb".to_owned()));
+    msg.nlas
+        .push(tc::Nla::Options(htb_class_options(peer.rate, peer.ceil)));
+    let flags = NLM_F_CREATE | NLM_F_REPLACE;
+    request(handle, RtnlMessage::NewTrafficClass(msg), flags, timeouts).await
+}
+
+async fn del_class(
+    handle: &Handle,
+    index: u32,
+    classid: u32,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut msg = TcMessage::with_index(index as i32);
+    msg.header.parent = ROOT_HANDLE;
+    msg.header.handle = classid;
+    match request(handle, RtnlMessage::DelTrafficClass(msg), 0, timeouts).await {
+        Err(WireCtlError::Io(e)) if e.raw_os_error() == Some(ENOENT) => Ok(()),
+        result => result,
+    }
+}
+
+async fn add_filter(
+    handle: &Handle,
+    index: u32,
+    priorities: (u16, u16),
+    classid: u32,
+    network: &IpNetwork,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let (priority, protocol) = match network {
+        IpNetwork::V4(_) => (priorities.0, ETH_P_IP),
+        IpNetwork::V6(_) => (priorities.1, ETH_P_IPV6),
+    };
+    let request = handle
+        .traffic_filter(index as i32)
+        .add()
+        .parent(ROOT_HANDLE)
+        .priority(priority)
+        // Protocols are in network byte order
+        .protocol(protocol.to_be())
+        .u32(vec![
+            cls_u32::Nla::Sel(destination_selector(network)),
+            cls_u32::Nla::ClassId(classid),
+        ])
+        .execute();
+    with_timeout(timeouts, TimeoutPhase::Write, async { Ok(request.await?) }).await
+}
+
+/// Delete the filters of the HTB qdisc with the priority and protocol of
+/// `info`
+async fn del_filters(
+    handle: &Handle,
+    index: u32,
+    info: u32,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let mut msg = TcMessage::with_index(index as i32);
+    msg.header.parent = ROOT_HANDLE;
+    msg.header.info = info;
+    match request(handle, RtnlMessage::DelTrafficFilter(msg), 0, timeouts).await {
+        Err(WireCtlError::Io(e)) if e.raw_os_error() == Some(ENOENT) => Ok(()),
+        result => result,
+    }
+}
+
+/// The classes and filters of the link
+async fn dump(
+    handle: &Handle,
+    index: u32,
+    timeouts: &Timeouts,
+) -> Result<(Vec<TcMessage>, Vec<TcMessage>), WireCtlError> {
+    let index = index as i32;
+    with_timeout(timeouts, TimeoutPhase::Read, async {
+        let classes: Vec<_> = handle
+            .traffic_class(index)
+            .get()
+            .execute()
+            .try_collect()
+            .await?;
+        let filters: Vec<_> = handle
+            .traffic_filter(index)
+            .get()
+            .execute()
+            .try_collect()
+            .await?;
+        Ok((classes, filters))
+    })
+    .await
+}
+
+/// Whether the root qdisc of the link is already the HTB qdisc `1:`
+async fn has_htb_root(
+    handle: &Handle,
+    index: u32,
+    timeouts: &Timeouts,
+) -> Result<bool, WireCtlError> {
+    let qdiscs: Vec<_> = with_timeout(timeouts, TimeoutPhase::Read, async {
+        Ok(handle
+            .qdisc()
+            .get()
+            .index(index as i32)
+            .execute()
+            .try_collect()
+            .await?)
+    })
+    .await?;
+    Ok(qdiscs.iter().any(|qdisc| {
+        qdisc.header.index == index as i32
+            && qdisc.header.parent == TC_H_ROOT
+            && qdisc.header.handle == ROOT_HANDLE
+            && qdisc.nlas.contains(&tc::Nla::Kind("htb".to_owned()))
+    }))
+}
+
+/// Replace the qdiscs of `ifname` with the limits of `shaping`
+///
+/// Peers which aren't on `device` are left out. The qdiscs and classes are
+/// changed in place, and the new filters are added before the previous ones
+/// are removed, so the traffic stays shaped during the update.
+pub(crate) async fn apply_shaping(
+    ifname: &str,
+    shaping: &Shaping,
+    device: &WgDevice,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    if !shaping.is_valid() {
+        return Err(WireCtlError::InvalidConfig);
+    }
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+
+    let (old_classes, old_filters) = dump(&handle, index, timeouts).await?;
+    let mut old_infos: Vec<u32> = old_filters
+        .iter()
+        .filter(|filter| filter.header.parent == ROOT_HANDLE)
+        .map(|filter| filter.header.info)
+        .collect();
+    old_infos.sort_unstable();
+    old_infos.dedup();
+    let in_use = |(ipv4, ipv6): (u16, u16)| {
+        old_infos
+            .iter()
+            .any(|info| [ipv4 as u32, ipv6 as u32].contains(&(info >> 16)))
+    };
+    let priorities = if in_use(PRIORITIES[0]) {
+        PRIORITIES[1]
+    } else {
+        PRIORITIES[0]
+    };
+
+    // HTB can't be changed, but its parameters are always the same. Any other
+    // root qdisc is replaced.
+    if !has_htb_root(&handle, index, timeouts).await? {
+        // struct tc_htb_glob, leaving unclassified packets unshaped
+        let mut glob = Vec::with_capacity(20);
+        glob.extend_from_slice(&TC_HTB_PROTOVER.to_ne_bytes());
+        glob.extend_from_slice(&HTB_RATE2QUANTUM.to_ne_bytes());
+        glob.extend_from_slice(&[0; 12]);
+        let options = vec![TcOpt::Other(DefaultNla::new(TCA_HTB_INIT, glob))];
+        replace_qdisc(
+            &handle,
+            index,
+            TC_H_ROOT,
+            ROOT_HANDLE,
+            "htb",
+            options,
+            timeouts,
+        )
+        .await?;
+    }
+
+    let mut classids = Vec::new();
+    let mut filters = Vec::new();
+    for (minor, peer) in (1..).zip(&shaping.peers) {
+        let Some(device_peer) = device
+            .peers
+            .iter()
+            .find(|device_peer| device_peer.public_key == peer.public_key)
+        else {
+            continue;
+        };
+        let classid = ROOT_HANDLE | minor;
+        replace_class(&handle, index, classid, peer, timeouts).await?;
+        replace_qdisc(&handle, index, classid, 0, "fq", Vec::new(), timeouts).await?;
+        classids.push(classid);
+        filters.extend(
+            device_peer
+                .allow_ips
+                .iter()
+                .map(|network| (classid, network)),
+        );
+    }
+
+    // Filters are tried in order, so the longest prefix has to come first, as
+    // for the routing of Wireguard
+    filters.sort_by_key(|(_, network)| std::cmp::Reverse(network.prefix()));
+    for (classid, network) in filters {
+        add_filter(&handle, index, priorities, classid, network, timeouts).await?;
+    }
+
+    // Classes can only be deleted once no filter refers to them
+    for info in old_infos {
+        let priority = (info >> 16) as u16;
+        if priority != priorities.0 && priority != priorities.1 {
+            del_filters(&handle, index, info, timeouts).await?;
+        }
+    }
+    for class in old_classes {
+        let classid = class.header.handle;
+        if classid & 0xffff_0000 == ROOT_HANDLE && !classids.contains(&classid) {
+            del_class(&handle, index, classid, timeouts).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The limits applied to the peers of `device` on `ifname`
+///
+/// Peers are recognized by the networks their classes are filtered on.
+pub(crate) async fn get_shaping(
+    ifname: &str,
+    device: &WgDevice,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<Shaping, WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    let (classes, filters) = dump(&handle, index, timeouts).await?;
+
+    let networks: Vec<(u32, IpNetwork)> = filters
+        .iter()
+        .filter_map(|filter| {
+            let ipv6 = match u16::from_be(filter.header.info as u16) {
+                ETH_P_IP => false,
+                ETH_P_IPV6 => true,
+                _ => return None,
+            };
+            let options = filter.nlas.iter().find_map(|nla| match nla {
+                tc::Nla::Options(options) => Some(options),
+                _ => None,
+            })?;
+            let (mut classid, mut network) = (None, None);
+            for option in options {
+                match option {
+                    TcOpt::U32(cls_u32::Nla::ClassId(id)) => classid = Some(*id),
+                    TcOpt::U32(cls_u32::Nla::Sel(sel)) => network = selector_network(sel, ipv6),
+                    _ => (),
+                }
+            }
+            Some((classid?, network?))
+        })
+        .collect();
+
+    let mut shaping = Shaping::new();
+    for class in &classes {
+        let is_htb = class.nlas.contains(&tc::Nla::Kind("htb".to_owned()));
+        if !is_htb || class.header.handle & 0xffff_0000 != ROOT_HANDLE {
+            continue;
+        }
+        let Some((rate, ceil)) = htb_class_rates(class) else {
+            continue;
+        };
+        let peer = networks
+            .iter()
+            .filter(|(classid, _)| *classid == class.header.handle)
+            .find_map(|(_, network)| {
+                device
+                    .peers
+                    .iter()
+                    .find(|peer| peer.allow_ips.contains(network))
+            });
+        if let Some(peer) = peer {
+            shaping =
+                shaping.add_peer(PeerShaping::new(peer.public_key.clone(), rate).set_ceil(ceil));
+        }
+    }
+    Ok(shaping)
+}
+
+/// Remove the qdiscs of `ifname`
+///
+/// Does nothing if the link has its default qdisc.
+pub(crate) async fn remove_shaping(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let handle = super::connect(netns)?;
+    let index = link_index(&handle, ifname, timeouts).await?;
+    del_root_qdisc(&handle, index, timeouts).await
+}
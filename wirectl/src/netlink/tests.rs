@@ -0,0 +1,42 @@
+use super::shaping::*;
+use ipnetwork::IpNetwork;
+use rtnetlink::packet::{tc, TcMessage};
+
+#[test]
+fn shaping_selector() {
+    for network in [
+        "10.9.0.2/32",
+        "192.168.0.0/16",
+        "0.0.0.0/0",
+        "fd00::/64",
+        "::/0",
+    ] {
+        let network: IpNetwork = network.parse().unwrap();
+        let sel = destination_selector(&network);
+        assert_eq!(sel.nkeys as usize, sel.keys.len());
+        assert_eq!(selector_network(&sel, network.is_ipv6()), Some(network));
+    }
+
+    // Only the words covered by the prefix are matched
+    let sel = destination_selector(&"fd00::/64".parse().unwrap());
+    assert_eq!(sel.keys.len(), 2);
+    assert_eq!(sel.keys[1].off, 28);
+    assert_eq!(sel.keys[0].val.to_ne_bytes(), [0xfd, 0, 0, 0]);
+}
+
+#[test]
+fn shaping_htb_class() {
+    let mut msg = TcMessage::default();
+    msg.nlas.push(tc::Nla::Kind("htb".to_owned()));
+    msg.nlas
+        .push(tc::Nla::Options(htb_class_options(10_000_000, 20_000_000)));
+    assert_eq!(htb_class_rates(&msg), Some((10_000_000, 20_000_000)));
+
+    // Rates above 32 bits of bytes per second
+    let mut msg = TcMessage::default();
+    msg.nlas.push(tc::Nla::Options(htb_class_options(
+        1_000_000,
+        80_000_000_000,
+    )));
+    assert_eq!(htb_class_rates(&msg), Some((1_000_000, 80_000_000_000)));
+}
@@ -0,0 +1,97 @@
+//! Network namespaces
+//!
+//! Operations targeting a namespace open their sockets from inside it: the
+//! calling thread enters the namespace just long enough to create them, and
+//! the sockets keep working there once the thread is back. Userspace
+//! implementations are launched inside the namespace the same way, so that
+//! their tunnel device is created there.
+//!
+//! Their configuration goes through their socket in
+//! [`WG_SOCKET_PATH`](crate::ipc::WG_SOCKET_PATH), connected from inside the
+//! namespace as well. The socket file belongs to the filesystem rather than to
+//! a namespace, so interface names are unique across namespaces.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), wirectl::WireCtlError> {
+//! use wirectl::{interface::WgInterface, netns::NetNs};
+//!
+//! // The UDP socket stays in the current namespace, the link moves
+//! let wgif = WgInterface::create_interface("wg0").await?;
+//! let wgif = wgif.move_to_netns(NetNs::named("container")).await?;
+//! wgif.set_link_up().await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::WireCtlError;
+use nix::sched::{setns, CloneFlags};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Where `ip netns add` mounts the namespaces it creates
+pub const NETNS_PATH: &str = "/run/netns";
+const THREAD_NETNS_PATH: &str = "/proc/thread-self/ns/net";
+
+/// A network namespace, referred to by a file such as `/run/netns/<name>` or
+/// `/proc/<pid>/ns/net`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NetNs {
+    path: PathBuf,
+}
+
+impl NetNs {
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The namespace `name` created by `ip netns add`
+    pub fn named(name: &str) -> Self {
+        Self::from_path(Path::new(NETNS_PATH).join(name))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn open(&self) -> Result<File, WireCtlError> {
+        Ok(File::open(&self.path)?)
+    }
+}
+
+/// Brings the thread back to its namespace, even if the closure panics
+struct Restore(File);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Err(e) = setns(self.0.as_raw_fd(), CloneFlags::CLONE_NEWNET) {
+            // Carrying on would open any later socket of the thread in the
+            // wrong namespace
+            error!(
+                "Failed to restore the network namespace of the thread: {}",
+                e
+            );
+            std::process::abort();
+        }
+    }
+}
+
+/// Run `f` inside `netns`, or in the current namespace if `None`
+///
+/// `f` runs on the calling thread, and must not block if that thread runs futures.
+pub(crate) fn run_in<T>(netns: Option<&NetNs>, f: impl FnOnce() -> T) -> Result<T, WireCtlError> {
+    let Some(netns) = netns else {
+        return Ok(f());
+    };
+    let target = netns.open()?;
+    let restore = Restore(File::open(THREAD_NETNS_PATH)?);
+    setns(target.as_raw_fd(), CloneFlags::CLONE_NEWNET).map_err(io::Error::from)?;
+    let result = f();
+    drop(restore);
+    Ok(result)
+}
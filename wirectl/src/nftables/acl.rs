@@ -0,0 +1,144 @@
+use super::{
+    is_not_found, is_valid_ifname, match_ct_state, match_dport, match_iifname, match_l4proto,
+    match_network, match_nfproto, Batch, Expr, Hook, Rule, CT_STATE_ESTABLISHED, CT_STATE_RELATED,
+    IPPROTO_TCP, IPPROTO_UDP, NF_ACCEPT, NF_DROP,
+};
+use crate::{
+    acl::{Acl, AclAction, AclProtocol, AclRule, AclTarget},
+    netns::NetNs,
+    timeout::Timeouts,
+    types::{PublicKey, WgDevice},
+    WireCtlError,
+};
+use ipnetwork::IpNetwork;
+
+const NF_IP_PRI_FILTER: i32 = 0;
+
+fn acl_chain(ifname: &str) -> String {
+    format!("acl_{}", ifname)
+}
+
+fn verdict(action: AclAction) -> Expr {
+    match action {
+        AclAction::Allow => Expr::Verdict(NF_ACCEPT),
+        AclAction::Deny => Expr::Verdict(NF_DROP),
+    }
+}
+
+fn allowed_ips<'a>(device: &'a WgDevice, public_key: &PublicKey) -> &'a [IpNetwork] {
+    device
+        .peers
+        .iter()
+        .find(|peer| &peer.public_key == public_key)
+        .map(|peer| &peer.allow_ips[..])
+        .unwrap_or_default()
+}
+
+/// The nftables rules of one ACL rule, for each pair of source and
+/// destination networks of the same family
+fn compile_rule(ifname: &str, rule: &AclRule, device: &WgDevice) -> Vec<Rule> {
+    let any = ["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()];
+    let destinations = match &rule.to {
+        AclTarget::Peer(public_key) => allowed_ips(device, public_key),
+        AclTarget::Network(network) => std::slice::from_ref(network),
+        AclTarget::Any => &any[..],
+    };
+    let protocol = rule.protocol.map(|protocol| match protocol {
+        AclProtocol::Tcp => IPPROTO_TCP,
+        AclProtocol::Udp => IPPROTO_UDP,
+    });
+
+    let mut rules = Vec::new();
+    for source in allowed_ips(device, &rule.from) {
+        for destination in destinations {
+            if source.is_ipv4() != destination.is_ipv4() {
+                continue;
+            }
+            let mut exprs = match_iifname(ifname).to_vec();
+            exprs.extend(match_nfproto(source.is_ipv6()));
+            exprs.extend(match_network(source, true));
+            exprs.extend(match_network(destination, false));
+            if let Some(protocol) = protocol {
+                exprs.extend(match_l4proto(protocol));
+            }
+            if let Some(ports) = rule.ports {
+                exprs.extend(match_dport(ports.start, ports.end));
+            }
+            exprs.push(verdict(rule.action));
+            rules.push(exprs);
+        }
+    }
+    rules
+}
+
+/// Compile `acl` into the rules of the ACL chain of `ifname`
+///
+/// Rules involving peers which aren't on `device` match nothing, and are
+/// left out.
+pub(crate) fn compile_acl(
+    ifname: &str,
+    acl: &Acl,
+    device: &WgDevice,
+) -> Result<Vec<Rule>, WireCtlError> {
+    if !is_valid_ifname(ifname) || !acl.rules.iter().all(AclRule::is_valid) {
+        return Err(WireCtlError::InvalidConfig);
+    }
+
+    let mut rules = vec![[
+        &match_iifname(ifname)[..],
+        &match_ct_state(CT_STATE_ESTABLISHED | CT_STATE_RELATED),
+        &[Expr::Verdict(NF_ACCEPT)],
+    ]
+    .concat()];
+    for rule in &acl.rules {
+        rules.extend(compile_rule(ifname, rule, device));
+    }
+    if acl.default == AclAction::Deny {
+        rules.push([&match_iifname(ifname)[..], &[Expr::Verdict(NF_DROP)]].concat());
+    }
+    Ok(rules)
+}
+
+/// Filter the traffic coming from the peers of `device` with `acl`
+///
+/// The previous ACL of the interface is replaced in the same batch, so that
+/// no packet goes through unfiltered.
+pub(crate) async fn apply_acl(
+    ifname: &str,
+    acl: &Acl,
+    device: &WgDevice,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let rules = compile_acl(ifname, acl, device)?;
+    let chain = acl_chain(ifname);
+
+    let mut batch = Batch::new();
+    batch
+        .add_table()
+        .add_base_chain(&chain, "filter", Hook::Forward, NF_IP_PRI_FILTER)
+        .flush_chain(&chain);
+    for rule in &rules {
+        batch.add_rule(&chain, rule);
+    }
+    batch.send(netns, timeouts).await
+}
+
+/// Remove the ACL of `ifname`
+///
+/// Does nothing if there is none.
+pub(crate) async fn remove_acl(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    if !is_valid_ifname(ifname) {
+        return Err(WireCtlError::InvalidConfig);
+    }
+    let mut batch = Batch::new();
+    batch.del_chain(&acl_chain(ifname));
+    match batch.send(netns, timeouts).await {
+        Err(e) if is_not_found(&e) => Ok(()),
+        result => result,
+    }
+}
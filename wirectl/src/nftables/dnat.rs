@@ -0,0 +1,162 @@
+use super::{
+    dnat, enable_forwarding, is_not_found, is_valid_ifname, match_ct_state, match_dport,
+    match_iifname, match_l4proto, match_network, match_nfproto, match_oifname, Batch, Expr, Hook,
+    Rule, CT_STATE_ESTABLISHED, CT_STATE_RELATED, IPPROTO_TCP, IPPROTO_UDP, NF_ACCEPT,
+};
+use crate::{
+    acl::AclProtocol,
+    netns::NetNs,
+    portforward::{PortForward, PortForwarding},
+    timeout::Timeouts,
+    types::WgDevice,
+    WireCtlError,
+};
+use ipnetwork::IpNetwork;
+
+const NF_IP_PRI_NAT_DST: i32 = -100;
+const NF_IP_PRI_FILTER: i32 = 0;
+
+fn dnat_chain(ifname: &str) -> String {
+    format!("dnat_{}", ifname)
+}
+
+fn portfwd_chain(ifname: &str) -> String {
+    format!("portfwd_{}", ifname)
+}
+
+/// The first host address of each family in the allowed IPs of the peer
+fn tunnel_addresses(rule: &PortForward, device: &WgDevice) -> Vec<IpNetwork> {
+    let Some(peer) = device
+        .peers
+        .iter()
+        .find(|peer| peer.public_key == rule.peer)
+    else {
+        return Vec::new();
+    };
+    let hosts = || {
+        peer.allow_ips
+            .iter()
+            .filter(|network| network.prefix() == if network.is_ipv4() { 32 } else { 128 })
+    };
+    hosts()
+        .find(|network| network.is_ipv4())
+        .into_iter()
+        .chain(hosts().find(|network| network.is_ipv6()))
+        .copied()
+        .collect()
+}
+
+/// Compile `forwarding` into the rules of the DNAT and forward chains of
+/// `ifname`
+///
+/// Ports of peers which aren't on `device`, or have no host address in their
+/// allowed IPs, are left out.
+pub(crate) fn compile_port_forwarding(
+    ifname: &str,
+    forwarding: &PortForwarding,
+    device: &WgDevice,
+) -> Result<(Vec<Rule>, Vec<Rule>), WireCtlError> {
+    let ingress = &forwarding.ingress;
+    if !is_valid_ifname(ifname)
+        || !is_valid_ifname(ingress)
+        || ifname == ingress
+        || !forwarding.is_valid()
+    {
+        return Err(WireCtlError::InvalidConfig);
+    }
+
+    let mut dnat_rules = Vec::new();
+    let mut forward_rules = vec![[
+        &match_iifname(ifname)[..],
+        &match_oifname(ingress),
+        &match_ct_state(CT_STATE_ESTABLISHED | CT_STATE_RELATED),
+        &[Expr::Verdict(NF_ACCEPT)],
+    ]
+    .concat()];
+    for rule in &forwarding.rules {
+        let protocol = match rule.protocol {
+            AclProtocol::Tcp => IPPROTO_TCP,
+            AclProtocol::Udp => IPPROTO_UDP,
+        };
+        for address in tunnel_addresses(rule, device) {
+            dnat_rules.push(
+                [
+                    &match_iifname(ingress)[..],
+                    &match_nfproto(address.is_ipv6()),
+                    &match_l4proto(protocol),
+                    &match_dport(rule.public_port, rule.public_port),
+                    &dnat(address.ip(), rule.port),
+                ]
+                .concat(),
+            );
+            forward_rules.push(
+                [
+                    &match_iifname(ingress)[..],
+                    &match_oifname(ifname),
+                    &match_nfproto(address.is_ipv6()),
+                    &match_network(&address, false),
+                    &match_l4proto(protocol),
+                    &match_dport(rule.port, rule.port),
+                    &[Expr::Verdict(NF_ACCEPT)],
+                ]
+                .concat(),
+            );
+        }
+    }
+    Ok((dnat_rules, forward_rules))
+}
+
+/// Forward the ports of `forwarding` to the peers of `device`
+///
+/// The previous rules of the interface are replaced, and IP forwarding is
+/// enabled in the kernel.
+pub(crate) async fn apply_port_forwarding(
+    ifname: &str,
+    forwarding: &PortForwarding,
+    device: &WgDevice,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    let (dnat_rules, forward_rules) = compile_port_forwarding(ifname, forwarding, device)?;
+    let dnat = dnat_chain(ifname);
+    let forward = portfwd_chain(ifname);
+
+    let mut batch = Batch::new();
+    batch
+        .add_table()
+        .add_base_chain(&dnat, "nat", Hook::Prerouting, NF_IP_PRI_NAT_DST)
+        .flush_chain(&dnat);
+    for rule in &dnat_rules {
+        batch.add_rule(&dnat, rule);
+    }
+    batch
+        .add_base_chain(&forward, "filter", Hook::Forward, NF_IP_PRI_FILTER)
+        .flush_chain(&forward);
+    for rule in &forward_rules {
+        batch.add_rule(&forward, rule);
+    }
+    batch.send(netns, timeouts).await?;
+
+    enable_forwarding(netns).await
+}
+
+/// Remove the port forwarding rules of `ifname`
+///
+/// Does nothing if they don't exist.
+pub(crate) async fn remove_port_forwarding(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    if !is_valid_ifname(ifname) {
+        return Err(WireCtlError::InvalidConfig);
+    }
+    let mut batch = Batch::new();
+    batch
+        .del_chain(&dnat_chain(ifname))
+        .del_chain(&portfwd_chain(ifname));
+    match batch.send(netns, timeouts).await {
+        Err(e) if is_not_found(&e) => Ok(()),
+        result => result,
+    }
+}
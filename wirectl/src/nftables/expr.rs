@@ -0,0 +1,323 @@
+use super::Message;
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+
+const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_DATA_VERDICT: u16 = 2;
+const NFTA_VERDICT_CODE: u16 = 1;
+
+const NFTA_META_DREG: u16 = 1;
+const NFTA_META_KEY: u16 = 2;
+
+const NFTA_CMP_SREG: u16 = 1;
+const NFTA_CMP_OP: u16 = 2;
+const NFTA_CMP_DATA: u16 = 3;
+
+const NFTA_CT_DREG: u16 = 1;
+const NFTA_CT_KEY: u16 = 2;
+
+const NFTA_BITWISE_SREG: u16 = 1;
+const NFTA_BITWISE_DREG: u16 = 2;
+const NFTA_BITWISE_LEN: u16 = 3;
+const NFTA_BITWISE_MASK: u16 = 4;
+const NFTA_BITWISE_XOR: u16 = 5;
+
+const NFTA_IMMEDIATE_DREG: u16 = 1;
+const NFTA_IMMEDIATE_DATA: u16 = 2;
+
+const NFTA_NAT_TYPE: u16 = 1;
+const NFTA_NAT_FAMILY: u16 = 2;
+const NFTA_NAT_REG_ADDR_MIN: u16 = 3;
+const NFTA_NAT_REG_PROTO_MIN: u16 = 5;
+
+const NFT_REG_VERDICT: u32 = 0;
+const NFT_REG_1: u32 = 1;
+const NFT_REG_2: u32 = 2;
+
+const NFT_NAT_DNAT: u32 = 1;
+
+const NFTA_PAYLOAD_DREG: u16 = 1;
+const NFTA_PAYLOAD_BASE: u16 = 2;
+const NFTA_PAYLOAD_OFFSET: u16 = 3;
+const NFTA_PAYLOAD_LEN: u16 = 4;
+
+const NFT_CT_STATE: u32 = 0;
+
+const IFNAMSIZ: usize = 16;
+
+pub(crate) const NF_DROP: u32 = 0;
+pub(crate) const NF_ACCEPT: u32 = 1;
+
+pub(crate) const NFPROTO_IPV4: u8 = 2;
+pub(crate) const NFPROTO_IPV6: u8 = 10;
+
+pub(crate) const IPPROTO_TCP: u8 = 6;
+pub(crate) const IPPROTO_UDP: u8 = 17;
+
+pub(crate) const CT_STATE_ESTABLISHED: u32 = 1 << 1;
+pub(crate) const CT_STATE_RELATED: u32 = 1 << 2;
+
+/// Comparison operators of [`Expr::Cmp`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CmpOp {
+    Eq = 0,
+    Neq = 1,
+    Lte = 3,
+    Gte = 5,
+}
+
+/// Packet metadata loaded by [`Expr::Meta`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MetaKey {
+    /// Input interface name
+    IifName = 6,
+    /// Output interface name
+    OifName = 7,
+    /// `NFPROTO_IPV4` or `NFPROTO_IPV6`
+    NfProto = 15,
+    /// Transport protocol
+    L4Proto = 16,
+}
+
+/// Header read by [`Expr::Payload`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PayloadBase {
+    Network = 1,
+    Transport = 2,
+}
+
+/// A statement of a rule
+///
+/// Loads go into the first register, which comparisons then read, unless
+/// told otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Expr {
+    /// Load packet metadata
+    Meta(MetaKey),
+    /// Load `len` bytes at `offset` of a header
+    Payload {
+        base: PayloadBase,
+        offset: u32,
+        len: u32,
+    },
+    /// Load the conntrack state bits
+    CtState,
+    /// Keep the bits of the register in `mask`
+    And(Vec<u8>),
+    /// Compare the register, stopping the rule if it doesn't match
+    Cmp(CmpOp, Vec<u8>),
+    /// Load `data` into a register
+    Immediate(u32, Vec<u8>),
+    /// Accept or drop the packet
+    Verdict(u32),
+    /// Destination NAT to the address in the first register and the port in
+    /// the second one
+    Dnat { ipv6: bool },
+    /// Source NAT to the address of the output interface
+    Masquerade,
+}
+
+/// The statements of a rule, in order
+pub(crate) type Rule = Vec<Expr>;
+
+impl Expr {
+    pub(crate) fn encode(&self, msg: &mut Message) {
+        msg.begin_nested(NFTA_LIST_ELEM)
+            .put_str(NFTA_EXPR_NAME, self.name())
+            .begin_nested(NFTA_EXPR_DATA);
+
+        match self {
+            Expr::Meta(key) => {
+                msg.put_u32(NFTA_META_DREG, NFT_REG_1)
+                    .put_u32(NFTA_META_KEY, *key as u32);
+            }
+            Expr::Payload { base, offset, len } => {
+                msg.put_u32(NFTA_PAYLOAD_DREG, NFT_REG_1)
+                    .put_u32(NFTA_PAYLOAD_BASE, *base as u32)
+                    .put_u32(NFTA_PAYLOAD_OFFSET, *offset)
+                    .put_u32(NFTA_PAYLOAD_LEN, *len);
+            }
+            Expr::CtState => {
+                msg.put_u32(NFTA_CT_DREG, NFT_REG_1)
+                    .put_u32(NFTA_CT_KEY, NFT_CT_STATE);
+            }
+            Expr::And(mask) => {
+                msg.put_u32(NFTA_BITWISE_SREG, NFT_REG_1)
+                    .put_u32(NFTA_BITWISE_DREG, NFT_REG_1)
+                    .put_u32(NFTA_BITWISE_LEN, mask.len() as u32)
+                    .begin_nested(NFTA_BITWISE_MASK)
+                    .put(NFTA_DATA_VALUE, mask)
+                    .end_nested()
+                    .begin_nested(NFTA_BITWISE_XOR)
+                    .put(NFTA_DATA_VALUE, &vec![0; mask.len()])
+                    .end_nested();
+            }
+            Expr::Cmp(op, data) => {
+                msg.put_u32(NFTA_CMP_SREG, NFT_REG_1)
+                    .put_u32(NFTA_CMP_OP, *op as u32)
+                    .begin_nested(NFTA_CMP_DATA)
+                    .put(NFTA_DATA_VALUE, data)
+                    .end_nested();
+            }
+            Expr::Immediate(reg, data) => {
+                msg.put_u32(NFTA_IMMEDIATE_DREG, *reg)
+                    .begin_nested(NFTA_IMMEDIATE_DATA)
+                    .put(NFTA_DATA_VALUE, data)
+                    .end_nested();
+            }
+            Expr::Verdict(code) => {
+                msg.put_u32(NFTA_IMMEDIATE_DREG, NFT_REG_VERDICT)
+                    .begin_nested(NFTA_IMMEDIATE_DATA)
+                    .begin_nested(NFTA_DATA_VERDICT)
+                    .put_u32(NFTA_VERDICT_CODE, *code)
+                    .end_nested()
+                    .end_nested();
+            }
+            Expr::Dnat { ipv6 } => {
+                let family = if *ipv6 { NFPROTO_IPV6 } else { NFPROTO_IPV4 };
+                msg.put_u32(NFTA_NAT_TYPE, NFT_NAT_DNAT)
+                    .put_u32(NFTA_NAT_FAMILY, family as u32)
+                    .put_u32(NFTA_NAT_REG_ADDR_MIN, NFT_REG_1)
+                    .put_u32(NFTA_NAT_REG_PROTO_MIN, NFT_REG_2);
+            }
+            Expr::Masquerade => (),
+        }
+
+        msg.end_nested().end_nested();
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Expr::Meta(_) => "meta",
+            Expr::Payload { .. } => "payload",
+            Expr::CtState => "ct",
+            Expr::And(_) => "bitwise",
+            Expr::Cmp(..) => "cmp",
+            Expr::Immediate(..) | Expr::Verdict(_) => "immediate",
+            Expr::Dnat { .. } => "nat",
+            Expr::Masquerade => "masq",
+        }
+    }
+}
+
+/// Interface names are compared as zero padded `IFNAMSIZ` bytes
+fn ifname_bytes(ifname: &str) -> Vec<u8> {
+    let mut bytes = ifname.as_bytes().to_vec();
+    bytes.resize(IFNAMSIZ, 0);
+    bytes
+}
+
+/// Whether `ifname` can be matched by a rule
+pub(crate) fn is_valid_ifname(ifname: &str) -> bool {
+    !ifname.is_empty() && ifname.len() < IFNAMSIZ && !ifname.contains('\0')
+}
+
+pub(crate) fn match_iifname(ifname: &str) -> [Expr; 2] {
+    [
+        Expr::Meta(MetaKey::IifName),
+        Expr::Cmp(CmpOp::Eq, ifname_bytes(ifname)),
+    ]
+}
+
+pub(crate) fn match_oifname(ifname: &str) -> [Expr; 2] {
+    [
+        Expr::Meta(MetaKey::OifName),
+        Expr::Cmp(CmpOp::Eq, ifname_bytes(ifname)),
+    ]
+}
+
+/// Match IPv4 or IPv6 packets
+pub(crate) fn match_nfproto(ipv6: bool) -> [Expr; 2] {
+    let nfproto = if ipv6 { NFPROTO_IPV6 } else { NFPROTO_IPV4 };
+    [
+        Expr::Meta(MetaKey::NfProto),
+        Expr::Cmp(CmpOp::Eq, vec![nfproto]),
+    ]
+}
+
+/// Match a transport protocol, such as `IPPROTO_TCP`
+pub(crate) fn match_l4proto(protocol: u8) -> [Expr; 2] {
+    [
+        Expr::Meta(MetaKey::L4Proto),
+        Expr::Cmp(CmpOp::Eq, vec![protocol]),
+    ]
+}
+
+/// Match the source or destination address of packets of the family of
+/// `network`, which must be checked before
+///
+/// Nothing needs to be matched for a zero prefix.
+pub(crate) fn match_network(network: &IpNetwork, source: bool) -> Vec<Expr> {
+    if network.prefix() == 0 {
+        return Vec::new();
+    }
+    let (offset, address, mask) = match network {
+        IpNetwork::V4(net) => (
+            if source { 12 } else { 16 },
+            net.network().octets().to_vec(),
+            net.mask().octets().to_vec(),
+        ),
+        IpNetwork::V6(net) => (
+            if source { 8 } else { 24 },
+            net.network().octets().to_vec(),
+            net.mask().octets().to_vec(),
+        ),
+    };
+
+    let mut exprs = vec![Expr::Payload {
+        base: PayloadBase::Network,
+        offset,
+        len: address.len() as u32,
+    }];
+    if mask.iter().any(|&byte| byte != 0xff) {
+        exprs.push(Expr::And(mask));
+    }
+    exprs.push(Expr::Cmp(CmpOp::Eq, address));
+    exprs
+}
+
+/// Match the destination port of TCP or UDP packets, whose protocol must be
+/// checked before
+pub(crate) fn match_dport(start: u16, end: u16) -> Vec<Expr> {
+    let dport = Expr::Payload {
+        base: PayloadBase::Transport,
+        offset: 2,
+        len: 2,
+    };
+    // Ports are big endian, so that comparing the bytes compares the numbers
+    if start == end {
+        vec![dport, Expr::Cmp(CmpOp::Eq, start.to_be_bytes().to_vec())]
+    } else {
+        vec![
+            dport,
+            Expr::Cmp(CmpOp::Gte, start.to_be_bytes().to_vec()),
+            Expr::Cmp(CmpOp::Lte, end.to_be_bytes().to_vec()),
+        ]
+    }
+}
+
+/// Redirect packets to `address` and `port`
+pub(crate) fn dnat(address: IpAddr, port: u16) -> [Expr; 3] {
+    let (address, ipv6) = match address {
+        IpAddr::V4(address) => (address.octets().to_vec(), false),
+        IpAddr::V6(address) => (address.octets().to_vec(), true),
+    };
+    [
+        Expr::Immediate(NFT_REG_1, address),
+        Expr::Immediate(NFT_REG_2, port.to_be_bytes().to_vec()),
+        Expr::Dnat { ipv6 },
+    ]
+}
+
+/// Match packets whose conntrack state is one of `states`
+pub(crate) fn match_ct_state(states: u32) -> [Expr; 3] {
+    [
+        Expr::CtState,
+        Expr::And(states.to_ne_bytes().to_vec()),
+        Expr::Cmp(CmpOp::Neq, vec![0; 4]),
+    ]
+}
@@ -0,0 +1,330 @@
+//! Firewall configuration through nftables
+//!
+//! Messages are encoded by hand and sent over nfnetlink, without the `nft`
+//! command. Every rule lives in the `inet wirectl` table, so that the rules of
+//! the system and of the user are never touched, and each change is sent as a
+//! single batch which the kernel applies atomically.
+use crate::{
+    netns::{self, NetNs},
+    timeout::{with_timeout, TimeoutPhase, Timeouts},
+    WireCtlError,
+};
+use rtnetlink::sys::{
+    protocols::NETLINK_NETFILTER, AsyncSocket, AsyncSocketExt, SmolSocket, SocketAddr,
+};
+use std::io;
+
+mod acl;
+mod dnat;
+mod expr;
+mod nat;
+
+pub(crate) use acl::*;
+pub(crate) use dnat::*;
+pub(crate) use expr::*;
+pub(crate) use nat::*;
+
+/// The table owned by wirectl
+pub(crate) const TABLE: &str = "wirectl";
+
+pub(crate) const ENOENT: i32 = 2;
+
+const NLMSG_HEADER_LEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_CREATE: u16 = 0x400;
+const NLM_F_APPEND: u16 = 0x800;
+
+const NLA_F_NESTED: u16 = 0x8000;
+
+const NFNETLINK_V0: u8 = 0;
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+const NFNL_MSG_BATCH_BEGIN: u16 = 0x10;
+const NFNL_MSG_BATCH_END: u16 = 0x11;
+
+const NFPROTO_UNSPEC: u8 = 0;
+const NFPROTO_INET: u8 = 1;
+
+const NFT_MSG_NEWTABLE: u16 = 0;
+const NFT_MSG_NEWCHAIN: u16 = 3;
+const NFT_MSG_DELCHAIN: u16 = 5;
+const NFT_MSG_NEWRULE: u16 = 6;
+const NFT_MSG_DELRULE: u16 = 8;
+
+const NFTA_TABLE_NAME: u16 = 1;
+
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_POLICY: u16 = 5;
+const NFTA_CHAIN_TYPE: u16 = 7;
+
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+
+const NFTA_RULE_TABLE: u16 = 1;
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_EXPRESSIONS: u16 = 4;
+
+/// Netfilter hooks of the `inet` family
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Hook {
+    Prerouting = 0,
+    Forward = 2,
+    Postrouting = 4,
+}
+
+/// A netlink message being encoded
+///
+/// Integer attributes are big endian, as nftables expects them.
+pub(crate) struct Message {
+    buf: Vec<u8>,
+    nests: Vec<usize>,
+}
+
+impl Message {
+    fn new(msg_type: u16, flags: u16, family: u8, res_id: u16) -> Self {
+        let mut buf = Vec::with_capacity(128);
+        // Length and sequence number are filled in by `finish()`
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&msg_type.to_ne_bytes());
+        buf.extend_from_slice(&flags.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        // nfgenmsg
+        buf.push(family);
+        buf.push(NFNETLINK_V0);
+        buf.extend_from_slice(&res_id.to_be_bytes());
+        Self {
+            buf,
+            nests: Vec::new(),
+        }
+    }
+
+    fn nftables(msg_type: u16, flags: u16) -> Self {
+        Self::new(
+            NFNL_SUBSYS_NFTABLES << 8 | msg_type,
+            NLM_F_REQUEST | NLM_F_ACK | flags,
+            NFPROTO_INET,
+            0,
+        )
+    }
+
+    fn batch(msg_type: u16) -> Self {
+        Self::new(
+            msg_type,
+            NLM_F_REQUEST,
+            NFPROTO_UNSPEC,
+            NFNL_SUBSYS_NFTABLES,
+        )
+    }
+
+    pub(crate) fn put(&mut self, attr: u16, value: &[u8]) -> &mut Self {
+        self.buf
+            .extend_from_slice(&(4 + value.len() as u16).to_ne_bytes());
+        self.buf.extend_from_slice(&attr.to_ne_bytes());
+        self.buf.extend_from_slice(value);
+        self.pad();
+        self
+    }
+
+    pub(crate) fn put_u32(&mut self, attr: u16, value: u32) -> &mut Self {
+        self.put(attr, &value.to_be_bytes())
+    }
+
+    /// A NUL terminated string
+    pub(crate) fn put_str(&mut self, attr: u16, value: &str) -> &mut Self {
+        let mut bytes = Vec::with_capacity(value.len() + 1);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+        self.put(attr, &bytes)
+    }
+
+    /// Start a nested attribute, which ends with [`Message::end_nested()`]
+    pub(crate) fn begin_nested(&mut self, attr: u16) -> &mut Self {
+        self.nests.push(self.buf.len());
+        self.buf.extend_from_slice(&0u16.to_ne_bytes());
+        self.buf
+            .extend_from_slice(&(attr | NLA_F_NESTED).to_ne_bytes());
+        self
+    }
+
+    pub(crate) fn end_nested(&mut self) -> &mut Self {
+        let start = self.nests.pop().expect("unbalanced nested attribute");
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    fn pad(&mut self) {
+        while !self.buf.len().is_multiple_of(4) {
+            self.buf.push(0);
+        }
+    }
+
+    fn finish(mut self, seq: u32) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf[8..12].copy_from_slice(&seq.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// Changes to the wirectl table, applied all at once
+#[derive(Default)]
+pub(crate) struct Batch {
+    messages: Vec<Message>,
+}
+
+impl Batch {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the wirectl table if it doesn't exist yet
+    pub(crate) fn add_table(&mut self) -> &mut Self {
+        let mut msg = Message::nftables(NFT_MSG_NEWTABLE, NLM_F_CREATE);
+        msg.put_str(NFTA_TABLE_NAME, TABLE);
+        self.messages.push(msg);
+        self
+    }
+
+    /// Create a chain attached to `hook`, accepting packets by default
+    ///
+    /// `kind` is the type of the chain, such as `filter` or `nat`.
+    pub(crate) fn add_base_chain(
+        &mut self,
+        name: &str,
+        kind: &str,
+        hook: Hook,
+        priority: i32,
+    ) -> &mut Self {
+        let mut msg = Message::nftables(NFT_MSG_NEWCHAIN, NLM_F_CREATE);
+        msg.put_str(NFTA_CHAIN_TABLE, TABLE)
+            .put_str(NFTA_CHAIN_NAME, name)
+            .begin_nested(NFTA_CHAIN_HOOK)
+            .put_u32(NFTA_HOOK_HOOKNUM, hook as u32)
+            .put_u32(NFTA_HOOK_PRIORITY, priority as u32)
+            .end_nested()
+            .put_u32(NFTA_CHAIN_POLICY, NF_ACCEPT)
+            .put_str(NFTA_CHAIN_TYPE, kind);
+        self.messages.push(msg);
+        self
+    }
+
+    /// Remove all the rules of a chain
+    pub(crate) fn flush_chain(&mut self, name: &str) -> &mut Self {
+        let mut msg = Message::nftables(NFT_MSG_DELRULE, 0);
+        msg.put_str(NFTA_RULE_TABLE, TABLE)
+            .put_str(NFTA_RULE_CHAIN, name);
+        self.messages.push(msg);
+        self
+    }
+
+    /// Remove a chain and its rules
+    pub(crate) fn del_chain(&mut self, name: &str) -> &mut Self {
+        self.flush_chain(name);
+        let mut msg = Message::nftables(NFT_MSG_DELCHAIN, 0);
+        msg.put_str(NFTA_CHAIN_TABLE, TABLE)
+            .put_str(NFTA_CHAIN_NAME, name);
+        self.messages.push(msg);
+        self
+    }
+
+    /// Append a rule to a chain
+    pub(crate) fn add_rule(&mut self, chain: &str, exprs: &[Expr]) -> &mut Self {
+        let mut msg = Message::nftables(NFT_MSG_NEWRULE, NLM_F_CREATE | NLM_F_APPEND);
+        msg.put_str(NFTA_RULE_TABLE, TABLE)
+            .put_str(NFTA_RULE_CHAIN, chain)
+            .begin_nested(NFTA_RULE_EXPRESSIONS);
+        for expr in exprs {
+            expr.encode(&mut msg);
+        }
+        msg.end_nested();
+        self.messages.push(msg);
+        self
+    }
+
+    /// The datagram of the batch, and the number of acknowledgements to expect
+    fn encode(self, first_seq: u32) -> (Vec<u8>, usize) {
+        let count = self.messages.len();
+        let mut buf = Message::batch(NFNL_MSG_BATCH_BEGIN).finish(first_seq);
+        for (msg, seq) in self.messages.into_iter().zip(first_seq + 1..) {
+            buf.extend(msg.finish(seq));
+        }
+        buf.extend(Message::batch(NFNL_MSG_BATCH_END).finish(first_seq + count as u32 + 1));
+        (buf, count)
+    }
+
+    /// Apply the batch
+    ///
+    /// The first error reported by the kernel is returned as an I/O error, in
+    /// which case nothing was changed.
+    pub(crate) async fn send(
+        self,
+        netns: Option<&NetNs>,
+        timeouts: &Timeouts,
+    ) -> Result<(), WireCtlError> {
+        if self.messages.is_empty() {
+            return Ok(());
+        }
+        let mut socket = netns::run_in(netns, || SmolSocket::new(NETLINK_NETFILTER))??;
+        socket.socket_mut().bind_auto()?;
+        socket.socket_mut().connect(&SocketAddr::new(0, 0))?;
+
+        let (buf, mut pending) = self.encode(1);
+        with_timeout(timeouts, TimeoutPhase::Write, async {
+            Ok(socket.send(&buf).await?)
+        })
+        .await?;
+
+        with_timeout(timeouts, TimeoutPhase::Read, async {
+            while pending > 0 {
+                let (datagram, _) = socket.recv_from_full().await?;
+                for (msg_type, payload) in messages(&datagram) {
+                    match msg_type {
+                        NLMSG_ERROR => {
+                            let code = payload
+                                .get(0..4)
+                                .map(|code| i32::from_ne_bytes(code.try_into().unwrap()))
+                                .ok_or(WireCtlError::InvalidProtocol)?;
+                            if code != 0 {
+                                return Err(io::Error::from_raw_os_error(-code).into());
+                            }
+                            pending -= 1;
+                        }
+                        NLMSG_DONE => pending = 0,
+                        _ => (),
+                    }
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The type and payload of the netlink messages of a datagram
+fn messages(mut datagram: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let len = u32::from_ne_bytes(datagram.get(0..4)?.try_into().unwrap()) as usize;
+        if len < NLMSG_HEADER_LEN || len > datagram.len() {
+            return None;
+        }
+        let msg_type = u16::from_ne_bytes(datagram[4..6].try_into().unwrap());
+        let payload = &datagram[NLMSG_HEADER_LEN..len];
+        datagram = &datagram[((len + 3) & !3).min(datagram.len())..];
+        Some((msg_type, payload))
+    })
+}
+
+/// Whether `error` is the kernel reporting a missing table, chain or rule
+pub(crate) fn is_not_found(error: &WireCtlError) -> bool {
+    matches!(error, WireCtlError::Io(e) if e.raw_os_error() == Some(ENOENT))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+use super::{
+    is_not_found, is_valid_ifname, match_ct_state, match_iifname, match_oifname, Batch, Expr, Hook,
+    CT_STATE_ESTABLISHED, CT_STATE_RELATED, NF_ACCEPT,
+};
+use crate::{
+    netns::{self, NetNs},
+    timeout::Timeouts,
+    WireCtlError,
+};
+use std::fs;
+
+const NF_IP_PRI_FILTER: i32 = 0;
+const NF_IP_PRI_NAT_SRC: i32 = 100;
+
+const FORWARDING_SYSCTLS: [&str; 2] = [
+    "/proc/sys/net/ipv4/ip_forward",
+    "/proc/sys/net/ipv6/conf/all/forwarding",
+];
+
+fn forward_chain(ifname: &str) -> String {
+    format!("forward_{}", ifname)
+}
+
+fn nat_chain(ifname: &str) -> String {
+    format!("nat_{}", ifname)
+}
+
+/// Sysctls are those of the namespace they are opened in, so they are written
+/// from inside `netns`
+pub(super) async fn enable_forwarding(netns: Option<&NetNs>) -> Result<(), WireCtlError> {
+    netns::run_in(netns, || {
+        for path in FORWARDING_SYSCTLS {
+            if fs::read_to_string(path)?.trim() != "1" {
+                fs::write(path, "1\n")?;
+            }
+        }
+        Ok(())
+    })?
+}
+
+/// Forward and masquerade the traffic from `ifname` to `egress`
+///
+/// The rules of the interface are replaced if they already exist. The accept
+/// rules can't override a drop in the forward hook of another table.
+pub(crate) async fn enable_nat(
+    ifname: &str,
+    egress: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    if !is_valid_ifname(ifname) || !is_valid_ifname(egress) || ifname == egress {
+        return Err(WireCtlError::InvalidConfig);
+    }
+    let forward = forward_chain(ifname);
+    let nat = nat_chain(ifname);
+
+    let mut batch = Batch::new();
+    batch
+        .add_table()
+        .add_base_chain(&forward, "filter", Hook::Forward, NF_IP_PRI_FILTER)
+        .flush_chain(&forward)
+        .add_rule(
+            &forward,
+            &[
+                &match_iifname(ifname)[..],
+                &match_oifname(egress),
+                &[Expr::Verdict(NF_ACCEPT)],
+            ]
+            .concat(),
+        )
+        .add_rule(
+            &forward,
+            &[
+                &match_iifname(egress)[..],
+                &match_oifname(ifname),
+                &match_ct_state(CT_STATE_ESTABLISHED | CT_STATE_RELATED),
+                &[Expr::Verdict(NF_ACCEPT)],
+            ]
+            .concat(),
+        )
+        .add_base_chain(&nat, "nat", Hook::Postrouting, NF_IP_PRI_NAT_SRC)
+        .flush_chain(&nat)
+        .add_rule(
+            &nat,
+            &[
+                &match_iifname(ifname)[..],
+                &match_oifname(egress),
+                &[Expr::Masquerade],
+            ]
+            .concat(),
+        );
+    batch.send(netns, timeouts).await?;
+
+    enable_forwarding(netns).await
+}
+
+/// Remove the forwarding and masquerading rules of `ifname`
+///
+/// Does nothing if they don't exist. Forwarding stays enabled in the kernel,
+/// as other interfaces may rely on it.
+pub(crate) async fn disable_nat(
+    ifname: &str,
+    netns: Option<&NetNs>,
+    timeouts: &Timeouts,
+) -> Result<(), WireCtlError> {
+    if !is_valid_ifname(ifname) {
+        return Err(WireCtlError::InvalidConfig);
+    }
+    let mut batch = Batch::new();
+    batch
+        .del_chain(&forward_chain(ifname))
+        .del_chain(&nat_chain(ifname));
+    match batch.send(netns, timeouts).await {
+        Err(e) if is_not_found(&e) => Ok(()),
+        result => result,
+    }
+}
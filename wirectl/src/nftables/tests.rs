@@ -0,0 +1,210 @@
+use super::*;
+use crate::{
+    acl::*,
+    portforward::*,
+    types::{Peer, PublicKey, WgDevice},
+};
+
+#[test]
+fn nftables_encode_attributes() {
+    let mut msg = Message::nftables(NFT_MSG_NEWTABLE, NLM_F_CREATE);
+    msg.put_str(NFTA_TABLE_NAME, "wg")
+        .begin_nested(NFTA_CHAIN_HOOK)
+        .put_u32(NFTA_HOOK_HOOKNUM, 4)
+        .end_nested();
+    let buf = msg.finish(7);
+
+    assert_eq!(buf.len() % 4, 0);
+    assert_eq!(
+        u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as usize,
+        buf.len()
+    );
+    assert_eq!(u16::from_ne_bytes(buf[4..6].try_into().unwrap()), 10 << 8);
+    assert_eq!(
+        u16::from_ne_bytes(buf[6..8].try_into().unwrap()),
+        NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE
+    );
+    assert_eq!(u32::from_ne_bytes(buf[8..12].try_into().unwrap()), 7);
+    assert_eq!(&buf[16..20], &[NFPROTO_INET, 0, 0, 0]);
+
+    // Strings are NUL terminated, then padded
+    assert_eq!(u16::from_ne_bytes(buf[20..22].try_into().unwrap()), 7);
+    assert_eq!(&buf[24..28], b"wg\0\0");
+
+    // Integers are big endian inside nested attributes
+    assert_eq!(u16::from_ne_bytes(buf[28..30].try_into().unwrap()), 12);
+    assert_eq!(
+        u16::from_ne_bytes(buf[30..32].try_into().unwrap()),
+        NFTA_CHAIN_HOOK | NLA_F_NESTED
+    );
+    assert_eq!(&buf[36..40], &[0, 0, 0, 4]);
+    assert_eq!(buf.len(), 40);
+}
+
+#[test]
+fn nftables_encode_batch() {
+    let mut batch = Batch::new();
+    batch.add_table().add_rule(
+        "chain",
+        &[&match_iifname("wg0")[..], &[Expr::Masquerade]].concat(),
+    );
+    let (buf, acks) = batch.encode(1);
+    assert_eq!(acks, 2);
+
+    let parsed: Vec<_> = messages(&buf).map(|(msg_type, _)| msg_type).collect();
+    assert_eq!(
+        parsed,
+        vec![
+            NFNL_MSG_BATCH_BEGIN,
+            10 << 8 | NFT_MSG_NEWTABLE,
+            10 << 8 | NFT_MSG_NEWRULE,
+            NFNL_MSG_BATCH_END,
+        ]
+    );
+    // The batch is addressed to the nftables subsystem
+    assert_eq!(&buf[16..20], &[0, 0, 0, 10]);
+}
+
+#[test]
+fn nftables_ifname() {
+    assert!(is_valid_ifname("wg0"));
+    assert!(is_valid_ifname("a234567890abcde"));
+    assert!(!is_valid_ifname("a234567890abcdef"));
+    assert!(!is_valid_ifname(""));
+    assert_eq!(
+        match_oifname("eth0")[1],
+        Expr::Cmp(CmpOp::Eq, b"eth0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec())
+    );
+}
+
+#[test]
+fn nftables_match_network() {
+    let network = "10.0.0.0/8".parse().unwrap();
+    assert_eq!(
+        match_network(&network, true),
+        vec![
+            Expr::Payload {
+                base: PayloadBase::Network,
+                offset: 12,
+                len: 4
+            },
+            Expr::And(vec![255, 0, 0, 0]),
+            Expr::Cmp(CmpOp::Eq, vec![10, 0, 0, 0]),
+        ]
+    );
+    // Full prefixes need no mask
+    let host = "fd00::2/128".parse().unwrap();
+    assert_eq!(match_network(&host, false).len(), 2);
+    assert!(match_network(&"::/0".parse().unwrap(), true).is_empty());
+}
+
+fn acl_device() -> (WgDevice, PublicKey, PublicKey) {
+    let laptop = PublicKey::from([1; 32]);
+    let server = PublicKey::from([2; 32]);
+    let mut device = WgDevice::new("wg0");
+    for (public_key, allowed_ips) in [
+        (&laptop, vec!["10.0.0.2/32", "fd00::2/128"]),
+        (&server, vec!["10.0.0.3/32"]),
+    ] {
+        let mut peer = Peer::new(public_key.clone());
+        peer.allow_ips = allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect();
+        device.peers.push(peer);
+    }
+    (device, laptop, server)
+}
+
+#[test]
+fn nftables_compile_acl() {
+    let (device, laptop, server) = acl_device();
+    let acl = Acl::new(AclAction::Deny).add_rule(
+        AclRule::allow(laptop.clone(), AclTarget::Peer(server.clone()))
+            .set_protocol(AclProtocol::Tcp)
+            .set_ports(PortRange::new(8000, 8080)),
+    );
+    let rules = compile_acl("wg0", &acl, &device).unwrap();
+
+    // Established connections, the IPv4 pair of addresses, and the default
+    assert_eq!(rules.len(), 3);
+    assert_eq!(rules[1].last(), Some(&Expr::Verdict(NF_ACCEPT)));
+    assert!(rules[1].contains(&Expr::Cmp(CmpOp::Eq, vec![10, 0, 0, 2])));
+    assert!(rules[1].contains(&Expr::Cmp(CmpOp::Eq, vec![10, 0, 0, 3])));
+    assert!(rules[1].contains(&Expr::Cmp(CmpOp::Gte, 8000u16.to_be_bytes().to_vec())));
+    assert!(rules[1].contains(&Expr::Cmp(CmpOp::Lte, 8080u16.to_be_bytes().to_vec())));
+    assert_eq!(rules[2].last(), Some(&Expr::Verdict(NF_DROP)));
+
+    // Both families of the laptop are denied, and nothing is dropped by default
+    let acl = Acl::new(AclAction::Allow).add_rule(AclRule::deny(laptop, AclTarget::Any));
+    let rules = compile_acl("wg0", &acl, &device).unwrap();
+    assert_eq!(rules.len(), 3);
+    assert!(rules[1..]
+        .iter()
+        .all(|rule| rule.last() == Some(&Expr::Verdict(NF_DROP))));
+}
+
+#[test]
+fn nftables_compile_acl_peers() {
+    let (device, laptop, _) = acl_device();
+    // Rules of peers missing from the device match nothing
+    let missing = PublicKey::from([3; 32]);
+    let acl = Acl::new(AclAction::Allow)
+        .add_rule(AclRule::deny(missing.clone(), AclTarget::Any))
+        .add_rule(AclRule::deny(laptop.clone(), AclTarget::Peer(missing)));
+    assert_eq!(compile_acl("wg0", &acl, &device).unwrap().len(), 1);
+
+    // Ports need a protocol
+    let acl = Acl::new(AclAction::Allow)
+        .add_rule(AclRule::deny(laptop, AclTarget::Any).set_ports(PortRange::single(22)));
+    assert!(matches!(
+        compile_acl("wg0", &acl, &device),
+        Err(WireCtlError::InvalidConfig)
+    ));
+}
+
+#[test]
+fn nftables_compile_port_forwarding() {
+    let (mut device, laptop, server) = acl_device();
+    // The routed subnet is skipped for the host address
+    device.peers[1]
+        .allow_ips
+        .insert(0, "192.168.1.0/24".parse().unwrap());
+    let forwarding = PortForwarding::new("eth0")
+        .add_rule(PortForward::new(AclProtocol::Tcp, 2222, laptop, 22))
+        .add_rule(PortForward::new(AclProtocol::Udp, 53, server, 5353))
+        .add_rule(PortForward::new(
+            AclProtocol::Tcp,
+            80,
+            PublicKey::from([3; 32]),
+            80,
+        ));
+    let (dnat_rules, forward_rules) = compile_port_forwarding("wg0", &forwarding, &device).unwrap();
+
+    // Both families of the laptop, and IPv4 only for the server
+    assert_eq!(dnat_rules.len(), 3);
+    assert!(dnat_rules[0].ends_with(&dnat("10.0.0.2".parse().unwrap(), 22)));
+    assert!(dnat_rules[1].ends_with(&dnat("fd00::2".parse().unwrap(), 22)));
+    assert!(dnat_rules[2].ends_with(&dnat("10.0.0.3".parse().unwrap(), 5353)));
+    assert!(dnat_rules[2].contains(&Expr::Cmp(CmpOp::Eq, 53u16.to_be_bytes().to_vec())));
+    // Replies, then one rule per DNAT
+    assert_eq!(forward_rules.len(), 4);
+    assert!(forward_rules[3].contains(&Expr::Cmp(CmpOp::Eq, 5353u16.to_be_bytes().to_vec())));
+}
+
+#[test]
+fn nftables_port_forwarding_invalid() {
+    let (device, laptop, server) = acl_device();
+    let forwarding = PortForwarding::new("eth0")
+        .add_rule(PortForward::new(AclProtocol::Tcp, 2222, laptop, 22))
+        .add_rule(PortForward::new(AclProtocol::Tcp, 2222, server.clone(), 22));
+    assert!(!forwarding.is_valid());
+    assert!(matches!(
+        compile_port_forwarding("wg0", &forwarding, &device),
+        Err(WireCtlError::InvalidConfig)
+    ));
+
+    // The same port can be forwarded for another protocol, but not from the
+    // interface itself
+    let forwarding =
+        PortForwarding::new("wg0").add_rule(PortForward::new(AclProtocol::Udp, 2222, server, 22));
+    assert!(forwarding.is_valid());
+    assert!(compile_port_forwarding("wg0", &forwarding, &device).is_err());
+}
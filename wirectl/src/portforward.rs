@@ -0,0 +1,75 @@
+//! Port forwarding from the host to the peers of an interface
+//!
+//! Connections to a public port of the host are redirected with nftables DNAT
+//! to the tunnel address of a peer, the first host address (`/32` or `/128`)
+//! of each family in its allowed IPs. Replies must be routed back through the
+//! tunnel by the peer.
+//!
+//! ```
+//! use wirectl::acl::AclProtocol;
+//! use wirectl::portforward::*;
+//! use wirectl::types::PublicKey;
+//!
+//! let nas = PublicKey::from([1; 32]);
+//! let forwarding = PortForwarding::new("eth0")
+//!     .add_rule(PortForward::new(AclProtocol::Tcp, 2222, nas.clone(), 22))
+//!     .add_rule(PortForward::new(AclProtocol::Udp, 51413, nas, 51413));
+//! assert!(forwarding.is_valid());
+//! ```
+use crate::{acl::AclProtocol, types::*};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A public port forwarded to a port of a peer
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PortForward {
+    pub protocol: AclProtocol,
+    pub public_port: u16,
+    pub peer: PublicKey,
+    pub port: u16,
+}
+
+impl PortForward {
+    pub fn new(protocol: AclProtocol, public_port: u16, peer: PublicKey, port: u16) -> Self {
+        Self {
+            protocol,
+            public_port,
+            peer,
+            port,
+        }
+    }
+}
+
+/// The ports forwarded from the `ingress` interface
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PortForwarding {
+    pub ingress: String,
+    pub rules: Vec<PortForward>,
+}
+
+impl PortForwarding {
+    pub fn new(ingress: &str) -> Self {
+        Self {
+            ingress: ingress.to_owned(),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn add_rule(mut self, rule: PortForward) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether no port is zero and no public port is forwarded twice
+    pub fn is_valid(&self) -> bool {
+        self.rules.iter().enumerate().all(|(i, rule)| {
+            rule.public_port != 0
+                && rule.port != 0
+                && !self.rules[..i].iter().any(|other| {
+                    other.protocol == rule.protocol && other.public_port == rule.public_port
+                })
+        })
+    }
+}
@@ -0,0 +1,80 @@
+//! Bandwidth limits of the peers of an interface
+//!
+//! [`Shaping`] is applied as an HTB qdisc at the root of the interface, with a
+//! class per peer holding an fq qdisc. Packets are classified by destination
+//! address with u32 filters built from the allowed IPs of the peers, so only
+//! the traffic sent to the peers is shaped. Traffic to peers without a limit
+//! is not delayed.
+//!
+//! ```
+//! use wirectl::shaping::*;
+//! use wirectl::types::PublicKey;
+//!
+//! let guest = PublicKey::from([1; 32]);
+//! let shaping = Shaping::new().add_peer(PeerShaping::new(guest, 10_000_000).set_ceil(20_000_000));
+//! assert!(shaping.is_valid());
+//! ```
+use crate::types::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The most classes an HTB qdisc can hold
+const MAX_PEERS: usize = 0xfff0;
+
+/// Bandwidth limit of a peer, in bits per second
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerShaping {
+    pub public_key: PublicKey,
+    /// Guaranteed rate
+    pub rate: u64,
+    /// Rate the peer may reach by borrowing unused bandwidth
+    pub ceil: u64,
+}
+
+impl PeerShaping {
+    /// Limit the peer to `rate`, without borrowing
+    pub fn new(public_key: PublicKey, rate: u64) -> Self {
+        Self {
+            public_key,
+            rate,
+            ceil: rate,
+        }
+    }
+
+    pub fn set_ceil(mut self, ceil: u64) -> Self {
+        self.ceil = ceil;
+        self
+    }
+}
+
+/// Bandwidth limits of the peers of an interface
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Shaping {
+    pub peers: Vec<PeerShaping>,
+}
+
+impl Shaping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_peer(mut self, peer: PeerShaping) -> Self {
+        self.peers.push(peer);
+        self
+    }
+
+    /// Whether the rates are at least a byte per second, ceilings aren't
+    /// below the rates, and no peer is limited twice
+    pub fn is_valid(&self) -> bool {
+        self.peers.len() <= MAX_PEERS
+            && self.peers.iter().enumerate().all(|(i, peer)| {
+                peer.rate >= 8
+                    && peer.ceil >= peer.rate
+                    && !self.peers[..i]
+                        .iter()
+                        .any(|other| other.public_key == peer.public_key)
+            })
+    }
+}
@@ -0,0 +1,188 @@
+//! Live statistics of Wireguard interfaces
+//!
+//! [`StatsWatcher`] polls an interface periodically, and computes the transfer
+//! of each peer since the previous poll.
+use crate::{interface::WgInterface, types::*, WireCtlError};
+use futures::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use smol::Timer;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Statistics of a peer at one poll
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerStats {
+    pub public_key: PublicKey,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Bytes received since the previous poll
+    pub rx_delta: u64,
+    /// Bytes sent since the previous poll
+    pub tx_delta: u64,
+    /// Bytes received per second since the previous poll
+    pub rx_rate: f64,
+    /// Bytes sent per second since the previous poll
+    pub tx_rate: f64,
+    /// Time elapsed since the latest handshake, `None` if the peer never handshaked
+    pub handshake_age: Option<Duration>,
+    /// The transfer counters went backwards since the previous poll
+    ///
+    /// This happens when the peer was removed and added again, or the interface
+    /// was recreated. The deltas count the transfer since the reset.
+    pub counter_reset: bool,
+}
+
+/// Statistics of an interface at one poll
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatsSnapshot {
+    pub device_name: String,
+    pub timestamp: SystemTime,
+    /// Time elapsed since the previous poll, `None` for the first snapshot
+    pub interval: Option<Duration>,
+    pub peers: Vec<PeerStats>,
+}
+
+impl StatsSnapshot {
+    pub fn peer(&self, public_key: &PublicKey) -> Option<&PeerStats> {
+        self.peers.iter().find(|p| &p.public_key == public_key)
+    }
+
+    pub fn total_rx_rate(&self) -> f64 {
+        self.peers.iter().map(|p| p.rx_rate).sum()
+    }
+
+    pub fn total_tx_rate(&self) -> f64 {
+        self.peers.iter().map(|p| p.tx_rate).sum()
+    }
+}
+
+/// Polls an interface and produces [`StatsSnapshot`]s
+#[derive(Debug)]
+pub struct StatsWatcher {
+    wgif: WgInterface,
+    interval: Duration,
+    last_poll: Option<Instant>,
+    /// When the counters were read, which failed polls leave unchanged
+    last_sample: Option<Instant>,
+    counters: HashMap<PublicKey, (u64, u64)>,
+}
+
+impl StatsWatcher {
+    pub fn new(wgif: WgInterface) -> Self {
+        Self {
+            wgif,
+            interval: DEFAULT_STATS_INTERVAL,
+            last_poll: None,
+            last_sample: None,
+            counters: HashMap::new(),
+        }
+    }
+
+    pub fn set_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn interface(&self) -> &WgInterface {
+        &self.wgif
+    }
+
+    /// Wait for the next poll, and return its statistics
+    ///
+    /// The first call returns immediately.
+    pub async fn next_snapshot(&mut self) -> Result<StatsSnapshot, WireCtlError> {
+        if let Some(last_poll) = self.last_poll {
+            Timer::at(last_poll + self.interval).await;
+        }
+        self.last_poll = Some(Instant::now());
+
+        let device = self.wgif.get_config().await?;
+        Ok(self.update(&device, Instant::now(), SystemTime::now()))
+    }
+
+    /// Turn the watcher into an endless stream of snapshots
+    pub fn into_stream(self) -> impl Stream<Item = Result<StatsSnapshot, WireCtlError>> {
+        futures::stream::unfold(self, |mut watcher| async move {
+            let snapshot = watcher.next_snapshot().await;
+            Some((snapshot, watcher))
+        })
+    }
+
+    /// Compute the statistics of `device` against the previous successful poll
+    pub(crate) fn update(
+        &mut self,
+        device: &WgDevice,
+        now: Instant,
+        wall_time: SystemTime,
+    ) -> StatsSnapshot {
+        let interval = self
+            .last_sample
+            .map(|last| now.saturating_duration_since(last));
+        let secs = interval.map_or(0.0, |i| i.as_secs_f64());
+
+        let mut counters = HashMap::with_capacity(device.peers.len());
+        let peers = device
+            .peers
+            .iter()
+            .map(|peer| {
+                let (rx_delta, tx_delta, counter_reset) = match self.counters.get(&peer.public_key)
+                {
+                    Some(&(rx, tx)) => {
+                        let counter_reset = peer.rx_bytes < rx || peer.tx_bytes < tx;
+                        if counter_reset {
+                            (peer.rx_bytes, peer.tx_bytes, true)
+                        } else {
+                            (peer.rx_bytes - rx, peer.tx_bytes - tx, false)
+                        }
+                    }
+                    None => (0, 0, false),
+                };
+                counters.insert(peer.public_key.clone(), (peer.rx_bytes, peer.tx_bytes));
+
+                let handshake_age = peer
+                    .last_handshake
+                    .map(|t| wall_time.duration_since(t).unwrap_or_default());
+
+                PeerStats {
+                    public_key: peer.public_key.clone(),
+                    rx_bytes: peer.rx_bytes,
+                    tx_bytes: peer.tx_bytes,
+                    rx_delta,
+                    tx_delta,
+                    rx_rate: rate(rx_delta, secs),
+                    tx_rate: rate(tx_delta, secs),
+                    handshake_age,
+                    counter_reset,
+                }
+            })
+            .collect();
+
+        self.counters = counters;
+        self.last_sample = Some(now);
+
+        StatsSnapshot {
+            device_name: device.device_name.clone(),
+            timestamp: wall_time,
+            interval,
+            peers,
+        }
+    }
+}
+
+fn rate(delta: u64, secs: f64) -> f64 {
+    if secs > 0.0 {
+        delta as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests;
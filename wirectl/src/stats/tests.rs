@@ -0,0 +1,72 @@
+use super::*;
+use crate::api::WgApi;
+
+fn peer(pubkey: u8, rx_bytes: u64, tx_bytes: u64) -> Peer {
+    let mut peer = Peer::new(PublicKey::from([pubkey; WG_KEY_LEN]));
+    peer.rx_bytes = rx_bytes;
+    peer.tx_bytes = tx_bytes;
+    peer
+}
+
+fn device(peers: Vec<Peer>) -> WgDevice {
+    let mut device = WgDevice::new("test");
+    device.peers = peers;
+    device
+}
+
+#[test]
+fn stats_rates() {
+    let mut watcher = StatsWatcher::new(WgInterface::new("test", WgApi::IPC));
+    let start = Instant::now();
+    let wall_time = SystemTime::now();
+
+    let snapshot = watcher.update(&device(vec![peer(1, 1000, 500)]), start, wall_time);
+    assert_eq!(snapshot.interval, None);
+    assert_eq!(snapshot.peers[0].rx_delta, 0);
+    assert_eq!(snapshot.peers[0].rx_rate, 0.0);
+    assert_eq!(snapshot.peers[0].handshake_age, None);
+
+    let snapshot = watcher.update(
+        &device(vec![peer(1, 3000, 1500), peer(2, 10, 10)]),
+        start + Duration::from_secs(2),
+        wall_time + Duration::from_secs(2),
+    );
+    assert_eq!(snapshot.interval, Some(Duration::from_secs(2)));
+    let stats = snapshot.peer(&PublicKey::from([1; WG_KEY_LEN])).unwrap();
+    assert_eq!((stats.rx_delta, stats.tx_delta), (2000, 1000));
+    assert_eq!((stats.rx_rate, stats.tx_rate), (1000.0, 500.0));
+    assert!(!stats.counter_reset);
+    // A new peer has no previous counters
+    let stats = snapshot.peer(&PublicKey::from([2; WG_KEY_LEN])).unwrap();
+    assert_eq!((stats.rx_delta, stats.tx_delta), (0, 0));
+}
+
+#[test]
+fn stats_counter_reset() {
+    let mut watcher = StatsWatcher::new(WgInterface::new("test", WgApi::IPC));
+    let start = Instant::now();
+    let wall_time = SystemTime::now();
+
+    watcher.update(&device(vec![peer(1, 5000, 5000)]), start, wall_time);
+    let snapshot = watcher.update(
+        &device(vec![peer(1, 100, 6000)]),
+        start + Duration::from_secs(1),
+        wall_time + Duration::from_secs(1),
+    );
+    let stats = &snapshot.peers[0];
+    assert!(stats.counter_reset);
+    assert_eq!((stats.rx_delta, stats.tx_delta), (100, 6000));
+}
+
+#[test]
+fn stats_failed_poll_waits() {
+    let wgif = WgInterface::new("test_missing", WgApi::IPC);
+    let mut watcher = StatsWatcher::new(wgif);
+    smol::block_on(async {
+        assert!(watcher.next_snapshot().await.is_err());
+    });
+    // The next poll is paced from the failed one, but the rates are still
+    // computed from the last successful one
+    assert!(watcher.last_poll.is_some());
+    assert!(watcher.last_sample.is_none());
+}
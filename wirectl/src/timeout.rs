@@ -0,0 +1,122 @@
+//! Deadlines for control-plane operations
+//!
+//! Every request sent to a Wireguard implementation is split into three phases:
+//! connecting to the control channel, writing the request and reading the response.
+//! Each phase can be bounded by its own deadline. When a deadline expires, the
+//! operation fails with [`WireCtlError::Timeout`].
+//!
+//! Deadlines can be configured process-wide by [`set_default_timeouts()`], or
+//! per interface by [`crate::interface::WgInterface::with_timeouts()`].
+use crate::WireCtlError;
+use futures::Future;
+use std::{fmt, sync::RwLock, time::Duration};
+
+static DEFAULT_TIMEOUTS: RwLock<Timeouts> = RwLock::new(Timeouts::none());
+
+/// The phase of an operation which ran out of time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    /// Connecting to the control channel, which includes waiting for the
+    /// launcher of a userspace implementation to set it up
+    Connect,
+    Write,
+    Read,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "connect"),
+            TimeoutPhase::Write => write!(f, "write"),
+            TimeoutPhase::Read => write!(f, "read"),
+        }
+    }
+}
+
+/// Deadlines of each phase of a control-plane operation
+///
+/// `None` means the phase may wait forever.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub write: Option<Duration>,
+    pub read: Option<Duration>,
+}
+
+impl Timeouts {
+    /// No deadline at all
+    pub const fn none() -> Self {
+        Self {
+            connect: None,
+            write: None,
+            read: None,
+        }
+    }
+
+    /// Use the same deadline for every phase
+    pub const fn all(timeout: Duration) -> Self {
+        Self {
+            connect: Some(timeout),
+            write: Some(timeout),
+            read: Some(timeout),
+        }
+    }
+
+    pub fn set_connect(mut self, timeout: Duration) -> Self {
+        self.connect = Some(timeout);
+        self
+    }
+
+    pub fn set_write(mut self, timeout: Duration) -> Self {
+        self.write = Some(timeout);
+        self
+    }
+
+    pub fn set_read(mut self, timeout: Duration) -> Self {
+        self.read = Some(timeout);
+        self
+    }
+
+    pub(crate) fn get(&self, phase: TimeoutPhase) -> Option<Duration> {
+        match phase {
+            TimeoutPhase::Connect => self.connect,
+            TimeoutPhase::Write => self.write,
+            TimeoutPhase::Read => self.read,
+        }
+    }
+}
+
+/// Get the process-wide default deadlines
+pub fn default_timeouts() -> Timeouts {
+    *DEFAULT_TIMEOUTS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set the process-wide default deadlines
+///
+/// It is used by every operation which doesn't specify its own deadlines.
+pub fn set_default_timeouts(timeouts: Timeouts) {
+    *DEFAULT_TIMEOUTS.write().unwrap_or_else(|e| e.into_inner()) = timeouts;
+}
+
+/// Run `fut` within the deadline of `phase`
+///
+/// The future is dropped when the deadline expires.
+pub(crate) async fn with_timeout<F, T>(
+    timeouts: &Timeouts,
+    phase: TimeoutPhase,
+    fut: F,
+) -> Result<T, WireCtlError>
+where
+    F: Future<Output = Result<T, WireCtlError>>,
+{
+    match timeouts.get(phase) {
+        Some(timeout) => {
+            smol::future::or(fut, async move {
+                smol::Timer::after(timeout).await;
+                Err(WireCtlError::Timeout(phase))
+            })
+            .await
+        }
+        None => fut.await,
+    }
+}
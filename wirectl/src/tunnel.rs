@@ -0,0 +1,154 @@
+//! Routing all the traffic through a Wireguard interface
+//!
+//! [`FullTunnel`] sets up the same policy routing as `wg-quick` does when a
+//! peer allows `0.0.0.0/0` or `::/0`: the encrypted packets of the interface
+//! carry a firewall mark and keep using the main table, while all the other
+//! packets use a separate table whose default route goes through the tunnel.
+//!
+//! Strict reverse path filtering drops the replies to the tunnel unless the
+//! `net.ipv4.conf.all.src_valid_mark` sysctl is enabled, which is left to the
+//! caller.
+use crate::{interface::WgInterface, netlink, types::*, WireCtlError};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+
+/// The first table and firewall mark tried, the default port of Wireguard
+const FIRST_TABLE: u32 = 51820;
+
+/// Policy routing of a full tunnel
+///
+/// It is kept to tear the routing down, and can be stored between runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FullTunnel {
+    ifname: String,
+    table: u32,
+    fwmark: u32,
+    ipv4: bool,
+    ipv6: bool,
+    /// Whether the firewall mark was set by [`FullTunnel::setup()`]
+    owns_fwmark: bool,
+}
+
+impl FullTunnel {
+    /// Route all the traffic of the families the peers allow entirely
+    ///
+    /// The table is the first free number from 51820. The firewall mark of the
+    /// interface is kept if it has one, otherwise the table number is set as
+    /// the firewall mark. Fails with [`WireCtlError::InvalidConfig`] if no peer
+    /// allows `0.0.0.0/0` nor `::/0`. If a rule or route can't be added, what
+    /// was already set up is torn down again.
+    pub async fn setup(wgif: &WgInterface) -> Result<FullTunnel, WireCtlError> {
+        let timeouts = wgif.timeouts();
+        let device = wgif.get_config().await?;
+
+        let default_routes = device
+            .peers
+            .iter()
+            .flat_map(|peer| &peer.allow_ips)
+            .filter(|allowed_ip| allowed_ip.prefix() == 0);
+        let (mut ipv4, mut ipv6) = (false, false);
+        for allowed_ip in default_routes {
+            ipv4 |= allowed_ip.is_ipv4();
+            ipv6 |= allowed_ip.is_ipv6();
+        }
+        if !ipv4 && !ipv6 {
+            return Err(WireCtlError::InvalidConfig);
+        }
+
+        let table = netlink::free_table(FIRST_TABLE, wgif.netns(), &timeouts).await?;
+        let (fwmark, owns_fwmark) = match device.fwmark {
+            Some(fwmark) => (fwmark, false),
+            None => {
+                let conf = WgDeviceSetter::new(wgif.ifname()).set_fwmark(table);
+                wgif.set_config(conf).await?;
+                (table, true)
+            }
+        };
+
+        let tunnel = FullTunnel {
+            ifname: wgif.ifname().to_owned(),
+            table,
+            fwmark,
+            ipv4,
+            ipv6,
+            owns_fwmark,
+        };
+        for ipv6 in tunnel.families() {
+            let added = netlink::add_full_tunnel(
+                &tunnel.ifname,
+                tunnel.table,
+                fwmark,
+                ipv6,
+                wgif.netns(),
+                &timeouts,
+            )
+            .await;
+            if let Err(e) = added {
+                tunnel.teardown(wgif).await.ok();
+                return Err(e);
+            }
+        }
+        Ok(tunnel)
+    }
+
+    /// Remove the rules and routes, and the firewall mark if it was set by
+    /// [`FullTunnel::setup()`]
+    ///
+    /// The interface may already be removed.
+    pub async fn teardown(self, wgif: &WgInterface) -> Result<(), WireCtlError> {
+        if wgif.ifname() != self.ifname {
+            return Err(WireCtlError::InvalidConfig);
+        }
+        let timeouts = wgif.timeouts();
+        for ipv6 in self.families() {
+            netlink::del_full_tunnel(
+                &self.ifname,
+                self.table,
+                self.fwmark,
+                ipv6,
+                wgif.netns(),
+                &timeouts,
+            )
+            .await?;
+        }
+
+        if self.owns_fwmark {
+            let conf = WgDeviceSetter::new(&self.ifname).set_fwmark(0);
+            match wgif.set_config(conf).await {
+                Ok(()) => (),
+                Err(WireCtlError::Io(e)) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn ifname(&self) -> &str {
+        &self.ifname
+    }
+
+    pub fn table(&self) -> u32 {
+        self.table
+    }
+
+    pub fn fwmark(&self) -> u32 {
+        self.fwmark
+    }
+
+    pub fn has_ipv4(&self) -> bool {
+        self.ipv4
+    }
+
+    pub fn has_ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    /// `false` for IPv4 and `true` for IPv6
+    fn families(&self) -> impl Iterator<Item = bool> {
+        [(self.ipv4, false), (self.ipv6, true)]
+            .into_iter()
+            .filter_map(|(enabled, ipv6)| enabled.then_some(ipv6))
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Peer, PeerSetter, WgDevice, WgDeviceSetter};
+use super::{PresharedKey, PrivateKey, PublicKey};
+use ipnetwork::IpNetwork;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+/// The configuration of a device, without any runtime status
+///
+/// See [`WgDevice::config()`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceConfig {
+    pub device_name: String,
+    pub public_key: Option<PublicKey>,
+    pub private_key: Option<PrivateKey>,
+    pub fwmark: Option<u32>,
+    pub listen_port: u16,
+    pub peers: Vec<PeerConfig>,
+}
+
+/// The configuration of a peer, without any runtime status
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerConfig {
+    pub public_key: PublicKey,
+    pub preshared_key: Option<PresharedKey>,
+    pub endpoint: Option<SocketAddr>,
+    pub persistent_keepalive: Option<u16>,
+    pub allow_ips: Vec<IpNetwork>,
+}
+
+impl DeviceConfig {
+    pub fn peer(&self, public_key: &PublicKey) -> Option<&PeerConfig> {
+        self.peers.iter().find(|p| &p.public_key == public_key)
+    }
+
+    /// Changes which make a device match this configuration
+    ///
+    /// The existing peers are replaced. A listen port of `0` and an unset
+    /// fwmark are applied as well, so that a restored device doesn't keep
+    /// stale values.
+    pub fn to_setter(&self) -> WgDeviceSetter {
+        let mut setter = WgDeviceSetter::new(&self.device_name)
+            .set_fwmark(self.fwmark.unwrap_or(0))
+            .set_listen_port(self.listen_port)
+            .set_replace_peers();
+        if let Some(private_key) = &self.private_key {
+            setter = setter.set_private_key(private_key.clone());
+        }
+        for peer in &self.peers {
+            setter = setter.set_peer(peer.to_setter());
+        }
+        setter
+    }
+}
+
+impl PeerConfig {
+    /// Changes which make a peer match this configuration
+    pub fn to_setter(&self) -> PeerSetter {
+        let mut setter = PeerSetter::new(self.public_key.clone())
+            .set_preshared_key(self.preshared_key.clone().unwrap_or_default())
+            .set_persistent_keepalive(self.persistent_keepalive.unwrap_or(0))
+            .set_replace_allowed_ips()
+            .add_allowed_ips(&self.allow_ips);
+        if let Some(endpoint) = self.endpoint {
+            setter = setter.set_endpoint(endpoint);
+        }
+        setter
+    }
+}
+
+impl From<&Peer> for PeerConfig {
+    fn from(peer: &Peer) -> Self {
+        Self {
+            public_key: peer.public_key.clone(),
+            preshared_key: peer.preshared_key.clone(),
+            endpoint: peer.endpoint,
+            persistent_keepalive: peer.persistent_keepalive,
+            allow_ips: peer.allow_ips.clone(),
+        }
+    }
+}
+
+impl From<&WgDevice> for DeviceConfig {
+    fn from(device: &WgDevice) -> Self {
+        Self {
+            device_name: device.device_name.clone(),
+            public_key: device.public_key.clone(),
+            private_key: device.private_key.clone(),
+            fwmark: device.fwmark,
+            listen_port: device.listen_port,
+            peers: device.peers.iter().map(PeerConfig::from).collect(),
+        }
+    }
+}
+
+impl From<DeviceConfig> for WgDeviceSetter {
+    fn from(config: DeviceConfig) -> Self {
+        config.to_setter()
+    }
+}
+impl From<&DeviceConfig> for WgDeviceSetter {
+    fn from(config: &DeviceConfig) -> Self {
+        config.to_setter()
+    }
+}
@@ -1,8 +1,11 @@
-use super::{Peer, PeerSetter};
+use super::{DeviceConfig, DeviceStatus, Peer, PeerSetter, PeerStatus};
 use super::{PrivateKey, PublicKey};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+/// The state of a device
+///
+/// Unset values are `None`, and serialized as `null`.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WgDevice {
@@ -10,7 +13,7 @@ pub struct WgDevice {
     pub ifindex: u32,
     pub public_key: Option<PublicKey>,
     pub private_key: Option<PrivateKey>,
-    pub fwmark: u32,
+    pub fwmark: Option<u32>,
     pub listen_port: u16,
     pub peers: Vec<Peer>,
 }
@@ -22,7 +25,7 @@ impl WgDevice {
             ifindex: 0,
             public_key: None,
             private_key: None,
-            fwmark: 0,
+            fwmark: None,
             listen_port: 0,
             peers: Vec::new(),
         }
@@ -40,18 +43,82 @@ impl WgDevice {
         self.listen_port != 0
     }
 
+    /// The configuration part of the device
+    pub fn config(&self) -> DeviceConfig {
+        DeviceConfig::from(self)
+    }
+
+    /// The runtime status part of the device
+    pub fn status(&self) -> DeviceStatus {
+        DeviceStatus::from(self)
+    }
+
+    /// Compose a device from its configuration and runtime status
+    ///
+    /// Peers are matched by their public key. Peers missing from `status`
+    /// get an empty status, and peers missing from `config` are dropped.
+    pub fn from_parts(config: DeviceConfig, status: DeviceStatus) -> Self {
+        let peers = config
+            .peers
+            .into_iter()
+            .map(|peer| {
+                let status = status
+                    .peer(&peer.public_key)
+                    .cloned()
+                    .unwrap_or_else(|| PeerStatus::new(peer.public_key.clone()));
+                Peer::from_parts(peer, status)
+            })
+            .collect();
+
+        Self {
+            device_name: config.device_name,
+            ifindex: status.ifindex,
+            public_key: config.public_key,
+            private_key: config.private_key,
+            fwmark: config.fwmark,
+            listen_port: config.listen_port,
+            peers,
+        }
+    }
+
+    #[deprecated(since = "0.3.0", note = "use `fwmark.is_some()` instead")]
     pub fn has_fwmark(&self) -> bool {
-        self.fwmark != 0
+        self.fwmark.is_some()
     }
 }
 
-#[derive(Debug)]
+/// Changes to apply to a device
+///
+/// With the `serde` feature, it can be used as a declarative configuration format.
+/// Absent fields are left unchanged on the device.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct WgDeviceSetter {
+    #[cfg_attr(feature = "serde", serde(rename = "device_name"))]
     pub(crate) devname: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "private_key",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
     pub(crate) privkey: Option<PrivateKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub(crate) fwmark: Option<u32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub(crate) listen_port: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) replace_peers: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) peers: Vec<PeerSetter>,
 }
 
@@ -91,6 +158,14 @@ impl WgDeviceSetter {
         self.peers.push(peer);
         self
     }
+
+    pub fn device_name(&self) -> &str {
+        &self.devname
+    }
+
+    pub fn peers(&self) -> &[PeerSetter] {
+        &self.peers
+    }
 }
 
 impl From<WgDevice> for WgDeviceSetter {
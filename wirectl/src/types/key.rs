@@ -8,7 +8,7 @@ use x25519_dalek::StaticSecret;
 use zeroize::{Zeroize, Zeroizing};
 
 pub const WG_KEY_LEN: usize = 32;
-pub const WG_KEY_BASE64_LEN: usize = ((WG_KEY_LEN + 2) / 3) * 4;
+pub const WG_KEY_BASE64_LEN: usize = WG_KEY_LEN.div_ceil(3) * 4;
 pub const WG_KEY_HEX_LEN: usize = WG_KEY_LEN * 2;
 
 fn base64_decode_checklen(input: &str, buf: &mut [u8; WG_KEY_LEN]) -> Result<(), WireCtlError> {
@@ -36,7 +36,7 @@ fn hex_decode_checklen(input: &str, buf: &mut [u8; WG_KEY_LEN]) -> Result<(), Wi
     Ok(())
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct PublicKey(x25519_dalek::PublicKey);
 
 impl PublicKey {
@@ -247,84 +247,186 @@ impl TryFrom<&[u8]> for PresharedKey {
 mod serde_impl {
     use super::*;
     use serde::{
-        de::{Error as DeError, Visitor},
-        Deserialize, Serialize,
+        de::{Error as DeError, SeqAccess, Unexpected, Visitor},
+        Deserialize, Deserializer, Serialize, Serializer,
     };
-    pub const SERDE_EXPECTED_KEY_LEN: &str = "32 bytes key buffer";
+    pub const SERDE_EXPECTED_KEY: &str = "32 bytes key, or its base64 or hexadecimal string";
 
     #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
-    pub struct BufferVisitor;
+    pub struct KeyVisitor;
 
-    impl<'de> Visitor<'de> for BufferVisitor {
-        type Value = [u8; WG_KEY_LEN];
+    impl<'de> Visitor<'de> for KeyVisitor {
+        type Value = Zeroizing<[u8; WG_KEY_LEN]>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            write!(formatter, "{}", SERDE_EXPECTED_KEY_LEN)
+            write!(formatter, "{}", SERDE_EXPECTED_KEY)
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: DeError,
+        {
+            let mut buf = Zeroizing::new([0u8; WG_KEY_LEN]);
+            let rslt = match v.len() {
+                WG_KEY_BASE64_LEN => base64_decode_checklen(v, &mut buf),
+                WG_KEY_HEX_LEN => hex_decode_checklen(v, &mut buf),
+                len => return Err(E::invalid_length(len, &self)),
+            };
+            rslt.map_err(|_| E::invalid_value(Unexpected::Str("<key string>"), &self))?;
+            Ok(buf)
         }
 
         fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
         where
             E: DeError,
         {
-            Self::Value::try_from(v)
-                .map_err(|_| E::invalid_length(v.len(), &SERDE_EXPECTED_KEY_LEN))
+            <[u8; WG_KEY_LEN]>::try_from(v)
+                .map(Zeroizing::new)
+                .map_err(|_| E::invalid_length(v.len(), &self))
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut buf = Zeroizing::new([0u8; WG_KEY_LEN]);
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+            }
+            if seq.next_element::<u8>()?.is_some() {
+                return Err(A::Error::invalid_length(WG_KEY_LEN + 1, &self));
+            }
+            Ok(buf)
+        }
+    }
+
+    /// Keys are base64 strings in human-readable formats, and raw bytes otherwise
+    fn serialize_key<S>(key: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let encoded = Zeroizing::new(base64::encode(key));
+            serializer.serialize_str(&encoded)
+        } else {
+            serializer.serialize_bytes(key)
+        }
+    }
+
+    fn deserialize_key<'de, D>(deserializer: D) -> Result<Zeroizing<[u8; WG_KEY_LEN]>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            // Also accept the byte arrays produced by the previous versions
+            deserializer.deserialize_any(KeyVisitor)
+        } else {
+            deserializer.deserialize_bytes(KeyVisitor)
         }
     }
 
     impl Serialize for PublicKey {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
-            S: serde::Serializer,
+            S: Serializer,
         {
-            serializer.serialize_bytes(self.0.as_bytes())
+            serialize_key(self.0.as_bytes(), serializer)
         }
     }
 
     impl<'de> Deserialize<'de> for PublicKey {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
-            D: serde::Deserializer<'de>,
+            D: Deserializer<'de>,
         {
-            let buf = deserializer.deserialize_bytes(BufferVisitor)?;
-            Ok(Self::from(buf))
+            let buf = deserialize_key(deserializer)?;
+            Ok(Self::from(*buf))
         }
     }
 
     impl Serialize for PrivateKey {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
-            S: serde::Serializer,
+            S: Serializer,
         {
-            serializer.serialize_bytes(&self.0.to_bytes())
+            let buf = Zeroizing::new(self.0.to_bytes());
+            serialize_key(buf.as_ref(), serializer)
         }
     }
 
     impl<'de> Deserialize<'de> for PrivateKey {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
-            D: serde::Deserializer<'de>,
+            D: Deserializer<'de>,
         {
-            let buf = deserializer.deserialize_bytes(BufferVisitor)?;
-            Ok(Self::from(buf))
+            let buf = deserialize_key(deserializer)?;
+            Ok(Self::from(*buf))
         }
     }
 
     impl Serialize for PresharedKey {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
-            S: serde::Serializer,
+            S: Serializer,
         {
-            serializer.serialize_bytes(&self.0)
+            serialize_key(&self.0, serializer)
         }
     }
 
     impl<'de> Deserialize<'de> for PresharedKey {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
-            D: serde::Deserializer<'de>,
+            D: Deserializer<'de>,
+        {
+            let buf = deserialize_key(deserializer)?;
+            Ok(Self::from(*buf))
+        }
+    }
+}
+
+/// Serde adapter which hides private key material
+///
+/// Use it on the fields holding [`PrivateKey`] or [`PresharedKey`]:
+///
+/// ```
+/// # use serde::Serialize;
+/// # use wirectl::types::PrivateKey;
+/// #[derive(Serialize)]
+/// struct Interface {
+///     #[serde(serialize_with = "wirectl::types::redacted::serialize")]
+///     private_key: PrivateKey,
+///     #[serde(serialize_with = "wirectl::types::redacted::option::serialize")]
+///     backup_key: Option<PrivateKey>,
+/// }
+/// ```
+#[cfg(feature = "serde")]
+pub mod redacted {
+    use serde::Serializer;
+
+    pub const REDACTED: &str = "(hidden)";
+
+    pub fn serialize<T, S>(_value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized,
+        S: Serializer,
+    {
+        serializer.serialize_str(REDACTED)
+    }
+
+    pub mod option {
+        use super::REDACTED;
+        use serde::Serializer;
+
+        pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
         {
-            let buf = deserializer.deserialize_bytes(BufferVisitor)?;
-            Ok(Self::from(buf))
+            match value {
+                Some(_) => serializer.serialize_some(REDACTED),
+                None => serializer.serialize_none(),
+            }
         }
     }
 }
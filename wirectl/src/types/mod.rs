@@ -1,8 +1,14 @@
 //! Wireguard interface types
+mod config;
 mod device;
 mod key;
 mod peer;
+mod status;
+mod validate;
 
+pub use config::*;
 pub use device::*;
 pub use key::*;
 pub use peer::*;
+pub use status::*;
+pub use validate::*;
@@ -1,22 +1,27 @@
-use super::{PresharedKey, PublicKey};
+use super::{PeerConfig, PeerStatus, PresharedKey, PublicKey};
 use ipnetwork::IpNetwork;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
     time::SystemTime,
 };
 
+/// A peer of a device
+///
+/// Unset values are `None`, and serialized as `null`.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Peer {
     pub public_key: PublicKey,
-    pub preshared_key: PresharedKey,
-    pub endpoint: SocketAddr,
-    pub last_handshake: SystemTime,
+    pub preshared_key: Option<PresharedKey>,
+    pub endpoint: Option<SocketAddr>,
+    /// `None` if no handshake has been completed yet
+    pub last_handshake: Option<SystemTime>,
     pub rx_bytes: u64,
     pub tx_bytes: u64,
-    pub persistent_keepalive: u16,
+    /// `None` if persistent keepalive is off
+    pub persistent_keepalive: Option<u16>,
     pub allow_ips: Vec<IpNetwork>,
 }
 
@@ -24,24 +29,47 @@ impl Peer {
     pub fn new(pubkey: PublicKey) -> Self {
         Self {
             public_key: pubkey,
-            preshared_key: PresharedKey::default(),
-            endpoint: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
-            last_handshake: SystemTime::UNIX_EPOCH,
+            preshared_key: None,
+            endpoint: None,
+            last_handshake: None,
             rx_bytes: 0,
             tx_bytes: 0,
-            persistent_keepalive: 0,
+            persistent_keepalive: None,
             allow_ips: Vec::new(),
         }
     }
 
-    pub fn preshared_key_option(&self) -> Option<&PresharedKey> {
-        if self.has_preshared_key() {
-            Some(&self.preshared_key)
-        } else {
-            None
+    /// Compose a peer from its configuration and runtime status
+    ///
+    /// The public key of `config` is used.
+    pub fn from_parts(config: PeerConfig, status: PeerStatus) -> Self {
+        Self {
+            public_key: config.public_key,
+            preshared_key: config.preshared_key,
+            endpoint: config.endpoint,
+            last_handshake: status.last_handshake,
+            rx_bytes: status.rx_bytes,
+            tx_bytes: status.tx_bytes,
+            persistent_keepalive: config.persistent_keepalive,
+            allow_ips: config.allow_ips,
         }
     }
 
+    /// The configuration part of the peer
+    pub fn config(&self) -> PeerConfig {
+        PeerConfig::from(self)
+    }
+
+    /// The runtime status part of the peer
+    pub fn status(&self) -> PeerStatus {
+        PeerStatus::from(self)
+    }
+
+    #[deprecated(since = "0.3.0", note = "use `preshared_key.as_ref()` instead")]
+    pub fn preshared_key_option(&self) -> Option<&PresharedKey> {
+        self.preshared_key.as_ref()
+    }
+
     pub fn is_address_allowed(&self, addr: IpAddr) -> bool {
         for network in &self.allow_ips {
             match network {
@@ -69,28 +97,51 @@ impl Peer {
         !self.public_key.is_empty()
     }
 
+    #[deprecated(since = "0.3.0", note = "use `preshared_key.is_some()` instead")]
     pub fn has_preshared_key(&self) -> bool {
-        !self.preshared_key.is_empty()
+        self.preshared_key.is_some()
     }
 
+    #[deprecated(since = "0.3.0", note = "use `persistent_keepalive.is_some()` instead")]
     pub fn has_persistent_keepalive(&self) -> bool {
-        self.persistent_keepalive != 0
+        self.persistent_keepalive.is_some()
     }
 
+    #[deprecated(since = "0.3.0", note = "use `endpoint.is_some()` instead")]
     pub fn has_endpoint(&self) -> bool {
-        !self.endpoint.ip().is_unspecified()
+        self.endpoint.is_some()
     }
 }
 
-#[derive(Debug)]
+/// Changes to apply to a peer
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
 pub struct PeerSetter {
+    #[cfg_attr(feature = "serde", serde(rename = "public_key"))]
     pub(crate) pubkey: PublicKey,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub(crate) preshared_key: Option<PresharedKey>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub(crate) endpoint: Option<SocketAddr>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub(crate) persistent_keepalive: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) replace_allowed_ips: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) allowed_ips: Vec<IpNetwork>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) update_only: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub(crate) remove: bool,
 }
 
@@ -137,6 +188,22 @@ impl PeerSetter {
         self.replace_allowed_ips = true;
         self
     }
+
+    /// Only change the peer if it already exists
+    pub fn set_update_only(mut self) -> Self {
+        self.update_only = true;
+        self
+    }
+
+    /// Remove the peer, all the other changes are ignored
+    pub fn set_remove(mut self) -> Self {
+        self.remove = true;
+        self
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pubkey
+    }
 }
 
 impl From<Peer> for PeerSetter {
@@ -0,0 +1,64 @@
+use super::{Peer, PublicKey, WgDevice};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// The runtime status of a device, without any configuration
+///
+/// See [`WgDevice::status()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceStatus {
+    pub device_name: String,
+    pub ifindex: u32,
+    pub peers: Vec<PeerStatus>,
+}
+
+/// The runtime status of a peer, without any configuration
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PeerStatus {
+    pub public_key: PublicKey,
+    /// `None` if no handshake has been completed yet
+    pub last_handshake: Option<SystemTime>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl DeviceStatus {
+    pub fn peer(&self, public_key: &PublicKey) -> Option<&PeerStatus> {
+        self.peers.iter().find(|p| &p.public_key == public_key)
+    }
+}
+
+impl PeerStatus {
+    pub fn new(public_key: PublicKey) -> Self {
+        Self {
+            public_key,
+            last_handshake: None,
+            rx_bytes: 0,
+            tx_bytes: 0,
+        }
+    }
+}
+
+impl From<&Peer> for PeerStatus {
+    fn from(peer: &Peer) -> Self {
+        Self {
+            public_key: peer.public_key.clone(),
+            last_handshake: peer.last_handshake,
+            rx_bytes: peer.rx_bytes,
+            tx_bytes: peer.tx_bytes,
+        }
+    }
+}
+
+impl From<&WgDevice> for DeviceStatus {
+    fn from(device: &WgDevice) -> Self {
+        Self {
+            device_name: device.device_name.clone(),
+            ifindex: device.ifindex,
+            peers: device.peers.iter().map(PeerStatus::from).collect(),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use super::{PeerSetter, PublicKey, WgDevice, WgDeviceSetter};
+use crate::allowedips::normalize;
+use ipnetwork::IpNetwork;
+use std::{collections::HashSet, fmt};
+use thiserror::Error;
+
+/// A problem found in a [`WgDeviceSetter`]
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    #[error("Peer {} is specified more than once", .0.to_base64())]
+    DuplicatePeer(PublicKey),
+    #[error("Peer {} has the public key of the interface itself", .0.to_base64())]
+    PeerIsSelf(PublicKey),
+    #[error("Peer has an all-zero public key")]
+    EmptyPublicKey,
+    #[error("Allowed IP {allowed_ip} of peer {} has host bits set", .public_key.to_base64())]
+    AllowedIpHostBits {
+        public_key: PublicKey,
+        allowed_ip: IpNetwork,
+    },
+    #[error("Peer {} is removed, but other changes are also specified", .0.to_base64())]
+    RemoveWithChanges(PublicKey),
+}
+
+/// Every problem found in a [`WgDeviceSetter`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ValidationError> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl WgDeviceSetter {
+    /// Check the changes without touching the device
+    ///
+    /// All the problems are reported at once. The persistent keepalive needs no
+    /// checking, as every `u16` is a valid interval (`0` turns it off).
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let own_key = self.privkey.as_ref().map(PublicKey::from);
+        self.validate_with_key(own_key.as_ref())
+    }
+
+    /// Same as [`WgDeviceSetter::validate()`], but also check against the current
+    /// state of the device
+    ///
+    /// If the changes don't include a private key, the peers are checked
+    /// against the public key of `device` instead.
+    pub fn validate_for(&self, device: &WgDevice) -> Result<(), ValidationErrors> {
+        let own_key = match &self.privkey {
+            Some(privkey) => Some(PublicKey::from(privkey)),
+            None => device.public_key.clone(),
+        };
+        self.validate_with_key(own_key.as_ref())
+    }
+
+    fn validate_with_key(&self, own_key: Option<&PublicKey>) -> Result<(), ValidationErrors> {
+        let mut errors = Vec::new();
+        let mut seen = HashSet::with_capacity(self.peers.len());
+
+        for peer in &self.peers {
+            if !seen.insert(&peer.pubkey) {
+                errors.push(ValidationError::DuplicatePeer(peer.pubkey.clone()));
+            }
+            validate_peer(peer, own_key, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+}
+
+fn validate_peer(
+    peer: &PeerSetter,
+    own_key: Option<&PublicKey>,
+    errors: &mut Vec<ValidationError>,
+) {
+    if peer.pubkey.is_empty() {
+        errors.push(ValidationError::EmptyPublicKey);
+    }
+    if own_key == Some(&peer.pubkey) {
+        errors.push(ValidationError::PeerIsSelf(peer.pubkey.clone()));
+    }
+    if peer.remove
+        && (peer.preshared_key.is_some()
+            || peer.endpoint.is_some()
+            || peer.persistent_keepalive.is_some()
+            || peer.replace_allowed_ips
+            || !peer.allowed_ips.is_empty()
+            || peer.update_only)
+    {
+        errors.push(ValidationError::RemoveWithChanges(peer.pubkey.clone()));
+    }
+    for allowed_ip in &peer.allowed_ips {
+        if normalize(allowed_ip) != *allowed_ip {
+            errors.push(ValidationError::AllowedIpHostBits {
+                public_key: peer.pubkey.clone(),
+                allowed_ip: *allowed_ip,
+            });
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, SystemTime};
+use wirectl::types::*;
+
+fn pubkey(n: u8) -> PublicKey {
+    PublicKey::from([n; WG_KEY_LEN])
+}
+
+fn device() -> WgDevice {
+    let mut device = WgDevice::new("wg0");
+    device.ifindex = 7;
+    device.listen_port = 51820;
+    device.private_key = Some(PrivateKey::from([1; WG_KEY_LEN]));
+
+    let mut peer = Peer::new(pubkey(2));
+    peer.endpoint = Some("192.0.2.1:51820".parse().unwrap());
+    peer.persistent_keepalive = Some(25);
+    peer.allow_ips = vec!["10.0.0.2/32".parse().unwrap()];
+    peer.last_handshake = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    peer.rx_bytes = 148;
+    peer.tx_bytes = 92;
+    device.peers.push(peer);
+    device.peers.push(Peer::new(pubkey(3)));
+    device
+}
+
+#[test]
+fn split_and_compose() {
+    let device = device();
+    let config = device.config();
+    let status = device.status();
+
+    assert_eq!(config.peers.len(), 2);
+    assert_eq!(
+        config.peer(&pubkey(2)).unwrap().persistent_keepalive,
+        Some(25)
+    );
+    assert_eq!(status.ifindex, 7);
+    assert_eq!(status.peer(&pubkey(2)).unwrap().rx_bytes, 148);
+
+    // The status of the second peer is lost, it starts over
+    let mut status = status;
+    status.peers.truncate(1);
+    let composed = WgDevice::from_parts(config, status);
+    assert_eq!(composed.ifindex, 7);
+    assert_eq!(composed.listen_port, 51820);
+    assert_eq!(composed.peers[0].status(), device.peers[0].status());
+    assert_eq!(composed.peers[0].allow_ips, device.peers[0].allow_ips);
+    assert_eq!(composed.peers[1].status(), PeerStatus::new(pubkey(3)));
+}
+
+#[test]
+fn config_to_setter() {
+    let setter = WgDeviceSetter::from(device().config());
+
+    assert_eq!(setter.device_name(), "wg0");
+    assert_eq!(setter.peers().len(), 2);
+    assert_eq!(setter.peers()[0].public_key(), &pubkey(2));
+    assert_eq!(setter.validate(), Ok(()));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn config_and_status_serde() {
+    let device = device();
+
+    let config = serde_json::to_value(device.config()).unwrap();
+    let peer = &config["peers"][0];
+    assert_eq!(peer["persistent_keepalive"], 25);
+    assert!(peer.get("rx_bytes").is_none());
+    assert!(peer.get("last_handshake").is_none());
+
+    let status = serde_json::to_value(device.status()).unwrap();
+    let peer = &status["peers"][0];
+    assert_eq!(peer["rx_bytes"], 148);
+    assert!(status.get("private_key").is_none());
+    assert!(peer.get("allow_ips").is_none());
+    assert!(peer.get("endpoint").is_none());
+
+    let restored: DeviceConfig = serde_json::from_value(config).unwrap();
+    assert_eq!(restored.peers[0].allow_ips, device.peers[0].allow_ips);
+}
@@ -0,0 +1,47 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::interface::WgInterface;
+use wirectl::tunnel::FullTunnel;
+use wirectl::types::*;
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn full_tunnel() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+        wgif.set_link_up().await.unwrap();
+        assert!(matches!(
+            FullTunnel::setup(&wgif).await,
+            Err(WireCtlError::InvalidConfig)
+        ));
+
+        let peer = PeerSetter::new(PublicKey::from([1; WG_KEY_LEN]))
+            .add_allowed_ip("0.0.0.0/0".parse().unwrap());
+        wgif.add_peer(peer).await.unwrap();
+
+        let tunnel = FullTunnel::setup(&wgif).await.unwrap();
+        assert!(tunnel.has_ipv4() && !tunnel.has_ipv6());
+        assert_eq!(tunnel.table(), tunnel.fwmark());
+        let device = wgif.get_config().await.unwrap();
+        assert_eq!(device.fwmark, Some(tunnel.fwmark()));
+
+        tunnel.teardown(&wgif).await.unwrap();
+        assert_eq!(wgif.get_config().await.unwrap().fwmark, None);
+
+        // An existing firewall mark is kept, but never used as the table
+        let device = wgif.get_config().await.unwrap();
+        wgif.set_config(WgDeviceSetter::from(&device).set_fwmark(254))
+            .await
+            .unwrap();
+        let tunnel = FullTunnel::setup(&wgif).await.unwrap();
+        assert_eq!(tunnel.fwmark(), 254);
+        assert!(tunnel.table() >= 51820);
+        tunnel.teardown(&wgif).await.unwrap();
+        assert_eq!(wgif.get_config().await.unwrap().fwmark, Some(254));
+
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
@@ -0,0 +1,47 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::acl::*;
+use wirectl::interface::WgInterface;
+use wirectl::types::{PeerSetter, PublicKey};
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_acl() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let laptop = PublicKey::from([1; 32]);
+        let server = PublicKey::from([2; 32]);
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        // No ACL set
+        assert!(matches!(
+            wgif.apply_acl().await,
+            Err(WireCtlError::InvalidConfig)
+        ));
+
+        let acl = Acl::new(AclAction::Deny).add_rule(
+            AclRule::allow(laptop.clone(), AclTarget::Peer(server.clone()))
+                .set_protocol(AclProtocol::Tcp)
+                .set_ports(PortRange::single(22)),
+        );
+        let wgif = wgif.with_acl(acl);
+        wgif.apply_acl().await.unwrap();
+
+        // Adding and removing peers applies the ACL again
+        wgif.add_peer(
+            PeerSetter::new(laptop.clone()).add_allowed_ip("10.0.0.2/32".parse().unwrap()),
+        )
+        .await
+        .unwrap();
+        wgif.add_peer(PeerSetter::new(server).add_allowed_ip("10.0.0.3/32".parse().unwrap()))
+            .await
+            .unwrap();
+        wgif.remove_peer(&laptop).await.unwrap();
+
+        wgif.remove_acl().await.unwrap();
+        wgif.remove_acl().await.unwrap();
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
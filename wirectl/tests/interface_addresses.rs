@@ -0,0 +1,40 @@
+use futures::executor::block_on;
+use ipnetwork::IpNetwork;
+use rand::prelude::*;
+use wirectl::interface::WgInterface;
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_addresses() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        let v4: IpNetwork = "10.8.0.1/24".parse().unwrap();
+        let v6: IpNetwork = "fd00::1/64".parse().unwrap();
+        wgif.add_address(v4).await.unwrap();
+        wgif.add_address(v6).await.unwrap();
+        assert!(matches!(
+            wgif.add_address(v4).await,
+            Err(WireCtlError::AddressAlreadyExists(_))
+        ));
+        let addresses = wgif.addresses().await.unwrap();
+        assert!(addresses.contains(&v4) && addresses.contains(&v6));
+
+        let other: IpNetwork = "10.9.0.1/16".parse().unwrap();
+        wgif.set_addresses(&[other, v6]).await.unwrap();
+        let addresses = wgif.addresses().await.unwrap();
+        assert!(!addresses.contains(&v4));
+        assert!(addresses.contains(&other) && addresses.contains(&v6));
+
+        wgif.remove_address(&v6).await.unwrap();
+        assert!(matches!(
+            wgif.remove_address(&v6).await,
+            Err(WireCtlError::AddressNotFound(_))
+        ));
+
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
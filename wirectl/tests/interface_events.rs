@@ -0,0 +1,39 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::{
+    events::{InterfaceEventKind, InterfaceEventWatcher},
+    interface::WgInterface,
+};
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_events() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let mut watcher = InterfaceEventWatcher::new().await.unwrap();
+
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+        wgif.set_link_up().await.unwrap();
+        wgif.remove_interfaces().await.unwrap();
+
+        let mut kinds = Vec::new();
+        while kinds.last() != Some(&InterfaceEventKind::InterfaceRemoved) {
+            let events = watcher.next_events().await.unwrap();
+            kinds.extend(
+                events
+                    .into_iter()
+                    .filter(|event| event.ifname == ifname)
+                    .map(|event| event.kind),
+            );
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                InterfaceEventKind::InterfaceAdded,
+                InterfaceEventKind::LinkUp,
+                InterfaceEventKind::InterfaceRemoved,
+            ]
+        );
+    });
+}
@@ -0,0 +1,27 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::interface::WgInterface;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_link() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        wgif.set_mtu(1380).await.unwrap();
+        wgif.set_link_up().await.unwrap();
+        let info = wgif.link_info().await.unwrap();
+        assert_eq!(info.mtu, 1380);
+        assert!(info.is_up());
+        assert_eq!(wgif.get_config().await.unwrap().ifindex, info.index);
+
+        wgif.set_link_down().await.unwrap();
+        assert!(!wgif.link_info().await.unwrap().is_up());
+        let stats = wgif.link_stats().await.unwrap();
+        assert_eq!(stats.tx_errors, 0);
+
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
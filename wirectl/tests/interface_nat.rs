@@ -0,0 +1,26 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::interface::WgInterface;
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_nat() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        assert!(matches!(
+            wgif.enable_nat(&ifname).await,
+            Err(WireCtlError::InvalidConfig)
+        ));
+        wgif.enable_nat("eth0").await.unwrap();
+        // Enabling again replaces the rules
+        wgif.enable_nat("eth1").await.unwrap();
+        wgif.disable_nat().await.unwrap();
+        wgif.disable_nat().await.unwrap();
+
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
@@ -0,0 +1,54 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use std::process::Command;
+use wirectl::{interface::WgInterface, netns::NetNs, WireCtlError};
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_netns() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let suffix = hex::encode(rng.next_u32().to_ne_bytes());
+        let ifname = format!("test_{}", suffix);
+        let nsname = format!("test_{}", suffix);
+        let status = Command::new("ip")
+            .args(["netns", "add", &nsname])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+        let wgif = wgif.move_to_netns(NetNs::named(&nsname)).await.unwrap();
+        assert_eq!(wgif.netns(), Some(&NetNs::named(&nsname)));
+        let outside = WgInterface::get_interface(&ifname).await.unwrap();
+        assert!(outside.link_info().await.is_err());
+
+        let inside = WgInterface::get_interface_in(&ifname, NetNs::named(&nsname))
+            .await
+            .unwrap();
+        assert_eq!(inside.netns(), Some(&NetNs::named(&nsname)));
+        let listed = WgInterface::get_interfaces_in(NetNs::named(&nsname))
+            .await
+            .unwrap();
+        assert!(listed.iter().any(|wgif| wgif.ifname() == ifname));
+        let current = NetNs::from_path("/proc/self/ns/net");
+        assert!(matches!(
+            WgInterface::get_interface_in(&ifname, current).await,
+            Err(WireCtlError::NotFound)
+        ));
+
+        wgif.add_address("10.8.0.1/24".parse().unwrap())
+            .await
+            .unwrap();
+        wgif.set_link_up().await.unwrap();
+        let info = wgif.link_info().await.unwrap();
+        assert!(info.is_up());
+        assert_eq!(wgif.get_config().await.unwrap().ifindex, info.index);
+
+        wgif.remove_interfaces().await.unwrap();
+        Command::new("ip")
+            .args(["netns", "del", &nsname])
+            .status()
+            .unwrap();
+    });
+}
@@ -0,0 +1,49 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::acl::AclProtocol;
+use wirectl::interface::WgInterface;
+use wirectl::portforward::*;
+use wirectl::types::{PeerSetter, PublicKey};
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_port_forwarding() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let nas = PublicKey::from([1; 32]);
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        // No port forwarding set
+        assert!(matches!(
+            wgif.apply_port_forwarding().await,
+            Err(WireCtlError::InvalidConfig)
+        ));
+
+        let forwarding = PortForwarding::new("lo").add_rule(PortForward::new(
+            AclProtocol::Tcp,
+            2222,
+            nas.clone(),
+            22,
+        ));
+        let wgif = wgif.with_port_forwarding(forwarding);
+        wgif.apply_port_forwarding().await.unwrap();
+
+        // Changing the allowed IPs of the peer updates the rules
+        wgif.add_peer(PeerSetter::new(nas.clone()).add_allowed_ip("10.0.0.2/32".parse().unwrap()))
+            .await
+            .unwrap();
+        wgif.update_peer(
+            PeerSetter::new(nas)
+                .set_replace_allowed_ips()
+                .add_allowed_ip("10.0.0.3/32".parse().unwrap()),
+        )
+        .await
+        .unwrap();
+
+        wgif.remove_port_forwarding().await.unwrap();
+        wgif.remove_port_forwarding().await.unwrap();
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
@@ -0,0 +1,63 @@
+use futures::executor::block_on;
+use ipnetwork::IpNetwork;
+use rand::prelude::*;
+use std::process::Command;
+use wirectl::interface::WgInterface;
+use wirectl::netlink::RouteOptions;
+use wirectl::types::*;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_routes() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+        wgif.add_address("10.8.0.1/24".parse().unwrap())
+            .await
+            .unwrap();
+
+        let connected: IpNetwork = "10.8.0.2/32".parse().unwrap();
+        let remote: IpNetwork = "10.20.0.0/16".parse().unwrap();
+        let default: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        let foreign: IpNetwork = "10.30.0.0/16".parse().unwrap();
+        let peer = PeerSetter::new(PublicKey::from([1; WG_KEY_LEN]))
+            .add_allowed_ips(&[connected, remote, default, foreign]);
+        wgif.add_peer(peer).await.unwrap();
+
+        // Routes of other tools are left alone, even to an allowed IP
+        let status = Command::new("ip")
+            .args([
+                "route",
+                "add",
+                "10.30.0.0/16",
+                "dev",
+                &ifname,
+                "table",
+                "1000",
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let options = RouteOptions::default().set_table(1000).set_metric(10);
+        let changes = wgif.sync_routes(&options).await.unwrap();
+        assert_eq!(changes.added, vec![remote]);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.skipped, vec![foreign]);
+        assert!(wgif.sync_routes(&options).await.unwrap().is_empty());
+
+        wgif.remove_peer(&PublicKey::from([1; WG_KEY_LEN]))
+            .await
+            .unwrap();
+        let changes = wgif.sync_routes(&options).await.unwrap();
+        assert_eq!(changes.removed, vec![remote]);
+        let routes = Command::new("ip")
+            .args(["route", "show", "table", "1000", "dev", &ifname])
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&routes.stdout).contains("10.30.0.0/16"));
+
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
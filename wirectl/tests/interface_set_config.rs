@@ -0,0 +1,88 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+use wirectl::{interface::WgInterface, types::*, WireCtlError};
+
+/// Answer the requests on the socket of `ifname` like a userspace
+/// implementation, and record the `set` requests
+fn fake_daemon(ifname: &str, private_key: &PrivateKey) -> Arc<Mutex<Vec<String>>> {
+    fs::create_dir_all("/var/run/wireguard").unwrap();
+    let listener = UnixListener::bind(format!("/var/run/wireguard/{}.sock", ifname)).unwrap();
+    let get_response = format!(
+        "private_key={}\nlisten_port=51820\nerrno=0\n\n",
+        private_key.to_hex()
+    );
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\n" {
+                    break;
+                }
+                request.push_str(&line);
+            }
+
+            if request == "get=1\n" {
+                stream.write_all(get_response.as_bytes()).ok();
+            } else if request.starts_with("set=1\n") {
+                recorded.lock().unwrap().push(request);
+                stream.write_all(b"errno=0\n\n").ok();
+            }
+        }
+    });
+    requests
+}
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_set_config() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let private_key = PrivateKey::generate(&mut rng);
+        let requests = fake_daemon(&ifname, &private_key);
+        let wgif = WgInterface::get_interface(&ifname).await.unwrap();
+
+        // Host bits are rejected before anything is sent
+        let peer_key = PrivateKey::generate(&mut rng).public_key();
+        let peer = PeerSetter::new(peer_key.clone()).add_allowed_ip("10.0.0.1/24".parse().unwrap());
+        assert!(matches!(
+            wgif.add_peer(peer).await,
+            Err(WireCtlError::Validation(_))
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+
+        let peer = PeerSetter::new(peer_key.clone()).add_allowed_ip("10.0.0.0/24".parse().unwrap());
+        wgif.add_peer(peer).await.unwrap();
+        let request = requests.lock().unwrap().pop().unwrap();
+        assert!(request.contains(&format!("public_key={}\n", peer_key.to_hex())));
+        assert!(request.contains("allowed_ip=10.0.0.0/24\n"));
+
+        // The setter has no private key, the one of the device is used
+        let device = wgif.get_config().await.unwrap();
+        let conf =
+            WgDeviceSetter::from(&device).set_peer(PeerSetter::new(private_key.public_key()));
+        match wgif.set_config(conf).await {
+            Err(WireCtlError::Validation(errors)) => assert_eq!(
+                errors.0,
+                vec![ValidationError::PeerIsSelf(private_key.public_key())]
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(requests.lock().unwrap().is_empty());
+
+        fs::remove_file(format!("/var/run/wireguard/{}.sock", ifname)).unwrap();
+    });
+}
@@ -0,0 +1,46 @@
+use futures::executor::block_on;
+use rand::prelude::*;
+use wirectl::interface::WgInterface;
+use wirectl::shaping::*;
+use wirectl::types::{PeerSetter, PublicKey};
+use wirectl::WireCtlError;
+
+#[test]
+#[ignore = "test must be run as root"]
+fn interface_shaping() {
+    block_on(async {
+        let mut rng = thread_rng();
+        let ifname = format!("test_{}", hex::encode(rng.next_u32().to_ne_bytes()));
+        let guest = PublicKey::from([1; 32]);
+        let wgif = WgInterface::create_interface(&ifname).await.unwrap();
+
+        // No shaping set
+        assert!(matches!(
+            wgif.apply_shaping().await,
+            Err(WireCtlError::InvalidConfig)
+        ));
+
+        let shaping = Shaping::new().add_peer(PeerShaping::new(guest.clone(), 10_000_000));
+        let wgif = wgif.with_shaping(shaping.clone());
+        wgif.apply_shaping().await.unwrap();
+        // The peer isn't on the interface yet
+        assert!(wgif.get_shaping().await.unwrap().peers.is_empty());
+
+        wgif.add_peer(
+            PeerSetter::new(guest.clone()).add_allowed_ip("10.0.0.2/32".parse().unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(wgif.get_shaping().await.unwrap(), shaping);
+
+        // The limits are updated in place
+        let shaping = Shaping::new().add_peer(PeerShaping::new(guest, 20_000_000));
+        let wgif = wgif.with_shaping(shaping.clone());
+        wgif.apply_shaping().await.unwrap();
+        assert_eq!(wgif.get_shaping().await.unwrap(), shaping);
+
+        wgif.remove_shaping().await.unwrap();
+        assert!(wgif.get_shaping().await.unwrap().peers.is_empty());
+        wgif.remove_interfaces().await.unwrap();
+    });
+}
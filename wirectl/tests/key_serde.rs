@@ -0,0 +1,45 @@
+#![cfg(feature = "serde")]
+use serde::Serialize;
+use wirectl::types::{PresharedKey, PrivateKey, PublicKey};
+
+const PRIVKEY_HEX: &str = "e84b5a6d2717c1003a13b431570353dbaca9146cf150c5f8575680feba52027a";
+const PUBKEY_BASE64: &str = "uFmW/sycfx/G0lcqdu2hHVm80gvo5UOxXOS9hajnWjM=";
+
+#[test]
+fn key_serde_human_readable() {
+    let pubkey = PublicKey::from_base64(PUBKEY_BASE64).unwrap();
+    let json = serde_json::to_string(&pubkey).unwrap();
+    assert_eq!(json, format!("\"{}\"", PUBKEY_BASE64));
+    assert_eq!(serde_json::from_str::<PublicKey>(&json).unwrap(), pubkey);
+
+    // Hexadecimal strings are accepted as well
+    let privkey: PrivateKey = serde_json::from_str(&format!("\"{}\"", PRIVKEY_HEX)).unwrap();
+    assert_eq!(privkey.to_hex(), PRIVKEY_HEX);
+
+    // So are the byte arrays produced by the older versions
+    let bytes: Vec<u8> = (0..32).collect();
+    let psk: PresharedKey = serde_json::from_str(&serde_json::to_string(&bytes).unwrap()).unwrap();
+    assert_eq!(psk.as_ref(), bytes.as_slice());
+
+    assert!(serde_json::from_str::<PublicKey>("\"AAAA\"").is_err());
+}
+
+#[test]
+fn key_serde_redacted() {
+    #[derive(Serialize)]
+    struct Interface {
+        #[serde(serialize_with = "wirectl::types::redacted::serialize")]
+        private_key: PrivateKey,
+        #[serde(serialize_with = "wirectl::types::redacted::option::serialize")]
+        preshared_key: Option<PresharedKey>,
+    }
+
+    let interface = Interface {
+        private_key: PrivateKey::from_hex(PRIVKEY_HEX).unwrap(),
+        preshared_key: None,
+    };
+    assert_eq!(
+        serde_json::to_string(&interface).unwrap(),
+        r#"{"private_key":"(hidden)","preshared_key":null}"#
+    );
+}
@@ -0,0 +1,87 @@
+use wirectl::types::*;
+
+fn pubkey(n: u8) -> PublicKey {
+    PublicKey::from([n; WG_KEY_LEN])
+}
+
+#[test]
+fn validate_ok() {
+    let device = WgDevice::new("test");
+    let conf = WgDeviceSetter::from(&device)
+        .set_private_key(PrivateKey::from([1; WG_KEY_LEN]))
+        .set_peer(PeerSetter::new(pubkey(2)).add_allowed_ip("10.0.0.0/24".parse().unwrap()))
+        .set_peer(PeerSetter::new(pubkey(3)).set_remove());
+
+    assert_eq!(conf.validate(), Ok(()));
+    assert_eq!(conf.validate_for(&device), Ok(()));
+}
+
+#[test]
+fn validate_reports_all_errors() {
+    let privkey = PrivateKey::from([1; WG_KEY_LEN]);
+    let mut device = WgDevice::new("test");
+    device.public_key = Some(privkey.public_key());
+    let conf = WgDeviceSetter::from(&device)
+        .set_peer(PeerSetter::new(pubkey(2)))
+        .set_peer(PeerSetter::new(pubkey(2)).add_allowed_ip("10.0.0.1/24".parse().unwrap()))
+        .set_peer(PeerSetter::new(pubkey(0)))
+        .set_peer(
+            PeerSetter::new(pubkey(3))
+                .set_remove()
+                .set_persistent_keepalive(25),
+        )
+        .set_peer(PeerSetter::new(privkey.public_key()));
+
+    // The device key is only known by the device
+    assert_eq!(conf.validate().unwrap_err().0.len(), 4);
+
+    let errors = conf.validate_for(&device).unwrap_err();
+    assert_eq!(
+        errors.0,
+        vec![
+            ValidationError::DuplicatePeer(pubkey(2)),
+            ValidationError::AllowedIpHostBits {
+                public_key: pubkey(2),
+                allowed_ip: "10.0.0.1/24".parse().unwrap(),
+            },
+            ValidationError::EmptyPublicKey,
+            ValidationError::RemoveWithChanges(pubkey(3)),
+            ValidationError::PeerIsSelf(privkey.public_key()),
+        ]
+    );
+}
+
+#[test]
+fn validate_allowed_ip_host_bits() {
+    let conf = WgDeviceSetter::from(&WgDevice::new("test")).set_peer(
+        PeerSetter::new(pubkey(2))
+            .add_allowed_ip("10.0.0.0/24".parse().unwrap())
+            .add_allowed_ip("fd00::1/64".parse().unwrap())
+            .add_allowed_ip("10.0.1.1/32".parse().unwrap()),
+    );
+
+    assert_eq!(
+        conf.validate().unwrap_err().0,
+        vec![ValidationError::AllowedIpHostBits {
+            public_key: pubkey(2),
+            allowed_ip: "fd00::1/64".parse().unwrap(),
+        }]
+    );
+}
+
+#[test]
+fn validate_keepalive_range() {
+    // Intervals which don't fit in a u16 are rejected when deserializing
+    let document = |keepalive: u32| {
+        format!(
+            r#"{{
+                "device_name": "test",
+                "peers": [{{ "public_key": "{}", "persistent_keepalive": {} }}]
+            }}"#,
+            pubkey(2).to_base64(),
+            keepalive
+        )
+    };
+    assert!(serde_json::from_str::<WgDeviceSetter>(&document(65535)).is_ok());
+    assert!(serde_json::from_str::<WgDeviceSetter>(&document(65536)).is_err());
+}